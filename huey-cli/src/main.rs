@@ -1,4 +1,7 @@
-use huey_core::{ light::{ Color, Light, ColorXY }, HueBridge };
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use huey_core::{ home::HueHome, light::{ Color, Light, ColorXY }, pairing::{ PairOptions, PairProgress }, secret::KeyProtection, state::HueState, store::CredentialStore, transport::{ DryRunTransport, RecordingTransport, ReqwestTransport }, BridgeEvent, HueBridge, HueError };
 use clap::{ Parser, Subcommand, Args };
 
 /// Simple program to greet a person
@@ -6,6 +9,14 @@ use clap::{ Parser, Subcommand, Args };
 #[command(name = "light-rs")]
 #[command(about = "A tool for controlling hue lights.", long_about = None)]
 struct LightCli {
+    /// Passphrase used to encrypt stored bridge keys
+    #[arg(long, global = true)]
+    passphrase: Option<String>,
+
+    /// Store bridge keys unencrypted when no secret service is available
+    #[arg(long, global = true, conflicts_with = "passphrase")]
+    plaintext: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -14,19 +25,105 @@ struct LightCli {
 enum Commands {
     #[command(about = "Discover bridge ip", arg_required_else_help = false)]
     Discover,
-    #[command(about = "Request api key from bridge", arg_required_else_help = false)]
-    Pair,
+    Pair(PairArgs),
+    Bridges(BridgesArgs),
+    Home(HomeArgs),
     Light(LightArgs)
 }
 
 #[derive(Debug, Args)]
-#[command(about = "Control lights on bridge", arg_required_else_help = true)]
-struct LightArgs {
+#[command(about = "List resources across every paired bridge", arg_required_else_help = false)]
+struct HomeArgs {
+    #[command(subcommand)]
+    command: Option<HomeCommands>
+}
+
+#[derive(Debug, Subcommand)]
+enum HomeCommands {
+    Lights,
+    Rooms,
+    Scenes,
+}
+
+#[derive(Debug, Args)]
+#[command(about = "Manage paired bridges", arg_required_else_help = false)]
+struct BridgesArgs {
+    #[command(subcommand)]
+    command: Option<BridgesCommands>
+}
+
+#[derive(Debug, Subcommand)]
+enum BridgesCommands {
+    List,
+    Default {
+        bridge_id: String
+    },
+    Remove {
+        bridge_id: String
+    },
+    /// List the application keys registered on a bridge
+    Keys {
+        bridge_id: String
+    },
+    /// Revoke an application key, e.g. one left over from testing
+    Revoke {
+        bridge_id: String,
+        username: String,
+
+        /// Print the request instead of sending it to the bridge
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+
+        /// Revoke the key huey itself uses, which leaves the stored bridge unusable
+        #[arg(long)]
+        force: bool
+    },
+    /// Pair a new application key and revoke the stored one
+    Rotate {
+        bridge_id: String
+    },
+}
+
+#[derive(Debug, Args)]
+#[command(about = "Request api key from bridge", arg_required_else_help = false)]
+struct PairArgs {
+    /// Bridge ip, discovered when omitted
     #[arg(short = 'b', long = "bridge")]
-    bridge: String,
+    bridge: Option<String>,
+
+    #[arg(long, default_value = "huey")]
+    app_name: String,
 
-    #[arg(short = 'k', long = "key")]
-    key: String,
+    #[arg(long, default_value = "cli")]
+    device_name: String,
+
+    /// Seconds to wait for the link button
+    #[arg(short, long, default_value_t = 30)]
+    timeout: u64,
+}
+
+#[derive(Debug, Args)]
+#[command(about = "Control lights on bridge", arg_required_else_help = false)]
+struct LightArgs {
+    /// Bridge ip, taken from the paired bridges when omitted
+    #[arg(short = 'b', long = "bridge", requires = "key")]
+    bridge: Option<String>,
+
+    #[arg(short = 'k', long = "key", requires = "bridge")]
+    key: Option<String>,
+
+    /// Id of a paired bridge, the default bridge is used when omitted
+    #[arg(long = "id", conflicts_with = "bridge")]
+    bridge_id: Option<String>,
+
+    /// Save the bridge traffic, with keys redacted, as a test fixture
+    #[arg(long = "record")]
+    record: Option<PathBuf>,
+
+    /// Print the changes instead of sending them to the bridge. Also available on `bridges revoke`,
+    /// pairing and changes to the stored bridges are never dry run.
+    #[arg(long = "dry-run")]
+    dry_run: bool,
 
     #[command(subcommand)]
     command: Option<LightCommands>
@@ -50,23 +147,123 @@ enum LightCommands {
         #[arg(short, long)]
         brightness: Option<f64>
     },
+    /// Change the name shown for a light
+    Rename {
+        light_id: String,
+        name: String
+    },
+    /// Print lights whenever they change on the bridge
+    Watch,
 }
 
 #[tokio::main]
 async fn main() {
     let args = LightCli::parse();
+    let protection = match (args.passphrase, args.plaintext) {
+        (Some(passphrase), _) => KeyProtection::Passphrase(passphrase),
+        (None, true) => KeyProtection::Plaintext,
+        (None, false) => KeyProtection::preferred(),
+    };
+
     match args.command {
         Commands::Discover => {
             let result = HueBridge::discover().await;
             println!("{:?}", result);
         },
-        Commands::Pair => {
-            let result = HueBridge::pair("10.0.99.56".into()).await;
+        Commands::Pair(pair_args) => {
+            let bridge_ip = match pair_args.bridge {
+                Some(bridge_ip) => bridge_ip,
+                None => match HueBridge::discover().await {
+                    Ok(bridge_ip) => bridge_ip,
+                    Err(err) => {
+                        println!("{:?}", Err::<HueBridge, _>(err));
+                        return;
+                    }
+                }
+            };
+
+            let options = PairOptions::new(pair_args.app_name, pair_args.device_name)
+                .timeout(Duration::from_secs(pair_args.timeout));
+            let result = HueBridge::pair_with(bridge_ip, options, |progress| {
+                if let PairProgress::WaitingForLinkButton { remaining, .. } = progress {
+                    println!("Press the link button on the bridge ({}s left)", remaining.as_secs());
+                }
+            }).await;
+
+            if let Ok(bridge) = &result {
+                if let Err(err) = remember_bridge(bridge, &protection) {
+                    println!("Paired but failed to save bridge: {:?}", err);
+                }
+            }
             println!("{:?}", result);
         },
+        Commands::Bridges(bridges_args) => {
+            let result = manage_bridges(bridges_args.command.unwrap_or(BridgesCommands::List), &protection).await;
+            if let Err(err) = result {
+                println!("{:?}", err);
+            }
+        },
+        Commands::Home(home_args) => {
+            let home = match CredentialStore::load().and_then(|store| HueHome::from_store(&store.with_protection(protection))) {
+                Ok(home) => home,
+                Err(err) => {
+                    println!("{:?}", err);
+                    return;
+                }
+            };
+
+            match home_args.command.unwrap_or(HomeCommands::Lights) {
+                HomeCommands::Lights => {
+                    let lights = home.lights().await;
+                    lights.resources.iter().for_each(|light| println!("{} {}", light.id, light.resource.name));
+                    print_failed(&lights.failed);
+                },
+                HomeCommands::Rooms => {
+                    let rooms = home.rooms().await;
+                    rooms.resources.iter().for_each(|room| println!("{} {}", room.id, room.resource.name));
+                    print_failed(&rooms.failed);
+                },
+                HomeCommands::Scenes => {
+                    let scenes = home.scenes().await;
+                    scenes.resources.iter().for_each(|scene| println!("{} {}", scene.id, scene.resource.name));
+                    print_failed(&scenes.failed);
+                },
+            }
+        },
         Commands::Light(light_args) => {
-            let bridge = HueBridge::new(light_args.key, light_args.bridge);
+            let bridge = match resolve_bridge(light_args.bridge, light_args.key, light_args.bridge_id, &protection) {
+                Ok(bridge) => bridge,
+                Err(err) => {
+                    println!("{:?}", err);
+                    return;
+                }
+            };
+            let recorder = match (&light_args.record, ReqwestTransport::new()) {
+                (None, _) => None,
+                (Some(_), Ok(transport)) => Some(Arc::new(RecordingTransport::new(transport))),
+                (Some(_), Err(err)) => {
+                    println!("{:?}", err);
+                    return;
+                }
+            };
+            let bridge = match &recorder {
+                Some(recorder) => bridge.with_transport(recorder.clone()),
+                None => bridge,
+            };
+            let (bridge, dry_run) = match light_args.dry_run {
+                true => {
+                    let (bridge, withheld) = bridge.dry_run();
+                    (bridge, Some(withheld))
+                },
+                false => (bridge, None),
+            };
+            let mut events = bridge.subscribe();
             let light_command = light_args.command.unwrap_or(LightCommands::List);
+            // Watching never returns, so there would be no point where the fixture is saved.
+            if matches!(light_command, LightCommands::Watch) && (light_args.record.is_some() || light_args.dry_run) {
+                println!("--record and --dry-run can't be used with watch");
+                return;
+            }
             match light_command {
                 LightCommands::List => {
                     let result = Light::list_lights(&bridge).await;
@@ -89,7 +286,159 @@ async fn main() {
                         .await;
                     println!("{:?}", result);
                 },
+                LightCommands::Rename { light_id, name } => {
+                    let result = Light::rename_id(light_id, name)
+                        .on(&bridge)
+                        .await;
+                    println!("{:?}", result);
+                },
+                LightCommands::Watch => {
+                    let state = match HueState::load(bridge.clone()).await {
+                        Ok(state) => state,
+                        Err(err) => {
+                            println!("{:?}", err);
+                            return;
+                        }
+                    };
+
+                    let mut receiver = state.subscribe();
+                    let sync = state.clone();
+                    tokio::spawn(async move { sync.run().await });
+                    // Runs until interrupted, so bridge moves are stored as they happen.
+                    loop {
+                        let snapshot = receiver.borrow_and_update().clone();
+                        for light in snapshot.lights() {
+                            println!("{} {} on={} brightness={:.0}", light.id, light.name, light.is_on, light.brightness);
+                        }
+                        loop {
+                            tokio::select! {
+                                changed = receiver.changed() => match changed {
+                                    Ok(()) => break,
+                                    Err(_) => return,
+                                },
+                                Ok(BridgeEvent::IpChanged { bridge_id, new_ip, .. }) = events.recv() => update_stored_ip(&bridge_id, new_ip),
+                            }
+                        }
+                        println!();
+                    }
+                },
+            }
+
+            // Keep the stored ip current if the bridge had to be rediscovered.
+            while let Ok(BridgeEvent::IpChanged { bridge_id, new_ip, .. }) = events.try_recv() {
+                update_stored_ip(&bridge_id, new_ip);
+            }
+
+            if let Some(dry_run) = dry_run {
+                print_withheld(&dry_run);
+            }
+
+            if let (Some(path), Some(recorder)) = (light_args.record, recorder) {
+                if let Err(err) = recorder.fixture().save(&path) {
+                    println!("Failed to save fixture: {:?}", err);
+                }
             }
         }
     }
 }
+
+fn update_stored_ip(bridge_id: &str, new_ip: String) {
+    println!("Bridge {} moved to {}", bridge_id, new_ip);
+    if let Ok(mut store) = CredentialStore::load() {
+        store.update_ip(bridge_id, new_ip);
+        store.save().ok();
+    }
+}
+
+fn resolve_bridge(bridge_ip: Option<String>, key: Option<String>, bridge_id: Option<String>, protection: &KeyProtection) -> Result<HueBridge, HueError> {
+    if let (Some(bridge_ip), Some(key)) = (bridge_ip, key) {
+        return Ok(HueBridge::new(bridge_ip, key));
+    }
+
+    let store = CredentialStore::load()?.with_protection(protection.clone());
+    let bridge = match bridge_id {
+        Some(bridge_id) => store.bridge(&bridge_id)?,
+        None => store.default_bridge()?,
+    };
+    bridge.ok_or(HueError::InvalidData { msg: "no paired bridge found, run `pair` first".into() })
+}
+
+fn remember_bridge(bridge: &HueBridge, protection: &KeyProtection) -> Result<(), HueError> {
+    let mut store = CredentialStore::load()?.with_protection(protection.clone());
+    store.remember(bridge)?;
+    store.save()
+}
+
+async fn manage_bridges(command: BridgesCommands, protection: &KeyProtection) -> Result<(), HueError> {
+    let mut store = CredentialStore::load()?.with_protection(protection.clone());
+    match command {
+        BridgesCommands::List => {
+            for (bridge_id, stored) in store.bridges() {
+                let marker = if store.default_id() == Some(bridge_id.as_str()) { "*" } else { " " };
+                println!("{} {} {}", marker, bridge_id, stored.bridge_ip);
+            }
+        },
+        BridgesCommands::Default { bridge_id } => {
+            store.set_default(&bridge_id)?;
+            store.save()?;
+        },
+        BridgesCommands::Remove { bridge_id } => {
+            store.remove(&bridge_id)?;
+            store.save()?;
+        },
+        BridgesCommands::Keys { bridge_id } => {
+            for key in stored_bridge(&store, &bridge_id)?.application_keys().await? {
+                let marker = if key.current { "*" } else { " " };
+                println!("{} {} {} last used {}", marker, key.username, key.name, key.last_used.unwrap_or_default());
+            }
+        },
+        BridgesCommands::Revoke { bridge_id, username, dry_run, force } => {
+            let bridge = stored_bridge(&store, &bridge_id)?;
+            if username == bridge.username && !force {
+                return Err(HueError::InvalidData {
+                    msg: "this is the stored key, use `bridges rotate` to replace it or pass --force to revoke it anyway".into()
+                });
+            }
+            match dry_run {
+                true => {
+                    let (bridge, withheld) = bridge.dry_run();
+                    bridge.revoke_application_key(&username).await?;
+                    print_withheld(&withheld);
+                },
+                false => bridge.revoke_application_key(&username).await?,
+            }
+        },
+        BridgesCommands::Rotate { bridge_id } => {
+            let options = PairOptions::new("huey", "cli");
+            let rotation = stored_bridge(&store, &bridge_id)?.rotate_key(options, |progress| {
+                if let PairProgress::WaitingForLinkButton { remaining, .. } = progress {
+                    println!("Press the link button on the bridge ({}s left)", remaining.as_secs());
+                }
+            }).await?;
+
+            store.remember(&rotation.bridge)?;
+            store.save()?;
+            if let Err(err) = rotation.revoked {
+                println!("Stored the new key but failed to revoke the old one: {:?}", err);
+            }
+        },
+    }
+    Ok(())
+}
+
+fn stored_bridge(store: &CredentialStore, bridge_id: &str) -> Result<HueBridge, HueError> {
+    store.bridge(bridge_id)?.ok_or(HueError::InvalidData { msg: format!("unknown bridge {}", bridge_id) })
+}
+
+fn print_withheld(dry_run: &DryRunTransport) {
+    for request in dry_run.requests() {
+        println!("Would send {} {} {}", request.method, request.redacted_path(), request.body.unwrap_or_default());
+    }
+}
+
+// Bridges a home listing couldn't reach, printed after the resources of the others.
+fn print_failed(failed: &[(String, HueError)]) {
+    for (bridge_id, err) in failed {
+        println!("{} failed: {:?}", bridge_id, err);
+    }
+}
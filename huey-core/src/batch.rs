@@ -0,0 +1,513 @@
+// Applies many light transactions at once. The bridge only handles about ten light commands a
+// second and answers bursts with 429s, so requests are spread out and only a few are in flight.
+// When the same change is sent to every light of a room or zone, its grouped light is used
+// instead, which is a single request and changes all lights in sync.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{stream, StreamExt};
+use serde_json::{json, Map, Value};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::{ApiVersion, HueError, HueBridge, ResourceRef};
+use crate::graph::ResourceGraph;
+use crate::light::{Light, LightTransaction};
+use crate::resource::{self, Resource};
+use crate::v1;
+
+const DEFAULT_CONCURRENCY: usize = 3;
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+pub struct LightBatch {
+    transactions: Vec<LightTransaction>,
+    concurrency: usize,
+    interval: Duration,
+    group_lights: bool,
+    graph: Option<Arc<ResourceGraph>>,
+}
+
+impl Default for LightBatch {
+    fn default() -> Self {
+        LightBatch::new()
+    }
+}
+
+impl LightBatch {
+    pub fn new() -> LightBatch {
+        LightBatch { transactions: Vec::new(), concurrency: DEFAULT_CONCURRENCY, interval: DEFAULT_INTERVAL, group_lights: true, graph: None }
+    }
+
+    pub fn push(mut self, transaction: LightTransaction) -> Self {
+        self.transactions.push(transaction);
+        self
+    }
+
+    // Requests in flight at the same time, at least one.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    // Minimum time between the start of two requests.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    // Enabled by default, disable it to always send one command per light.
+    pub fn group_lights(mut self, group_lights: bool) -> Self {
+        self.group_lights = group_lights;
+        self
+    }
+
+    // Rooms and zones to find grouped lights in, e.g. `state.snapshot().graph()`. Without it the
+    // whole resource tree is fetched whenever the batch could use a grouped light.
+    pub fn graph(mut self, graph: ResourceGraph) -> Self {
+        self.graph = Some(Arc::new(graph));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    // Sends every transaction, a failed one doesn't stop the others.
+    #[tracing::instrument(name = "light_batch", skip_all, fields(transactions = self.transactions.len()))]
+    pub async fn on(&self, bridge: &HueBridge) -> BatchReport {
+        self.send(bridge, self.graph.as_deref()).await
+    }
+
+    async fn send(&self, bridge: &HueBridge, graph: Option<&ResourceGraph>) -> BatchReport {
+        let groups = if self.group_lights { self.find_groups(bridge, graph).await } else { Vec::new() };
+        let grouped = groups.iter().flat_map(|group| group.indices.iter().copied()).collect::<HashSet<_>>();
+        let jobs = groups.into_iter()
+            .map(Job::Group)
+            .chain((0..self.transactions.len()).filter(|index| !grouped.contains(index)).map(Job::Light));
+
+        let throttle = Throttle { interval: self.interval, next_slot: Mutex::new(Instant::now()) };
+        let mut results = stream::iter(jobs)
+            .map(|job| self.run(bridge, &throttle, job))
+            .buffer_unordered(self.concurrency)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        results.sort_by_key(|(index, _)| *index);
+
+        let results = results.into_iter()
+            .map(|(index, result)| BatchResult { light_id: self.transactions[index].light_id().to_string(), result })
+            .collect();
+        let report = BatchReport { results };
+        tracing::debug!(failed = report.failed().count(), "batch finished");
+        report
+    }
+
+    // Snapshots the affected lights first, and if any transaction fails, puts every light back the way
+    // it was. Only the fields the batch changes are restored. Fails without sending anything if the
+    // snapshot can't be taken. The batch and the rollback share one load of the resource tree.
+    #[tracing::instrument(name = "light_transaction", skip_all, fields(transactions = self.transactions.len()))]
+    pub async fn on_or_rollback(&self, bridge: &HueBridge) -> Result<TransactionReport, HueError> {
+        let (snapshot, loaded) = light_states(bridge, self.group_lights && self.graph.is_none()).await?;
+        let graph = self.graph.as_deref().or(loaded.as_ref());
+        let applied = self.send(bridge, graph).await;
+        if applied.is_success() {
+            return Ok(TransactionReport { applied, rollback: None });
+        }
+
+        // Several transactions for the same light are restored together.
+        let mut changes = Vec::<(&str, Value)>::new();
+        for transaction in &self.transactions {
+            match changes.iter_mut().find(|(light_id, _)| *light_id == transaction.light_id()) {
+                Some((_, body)) => resource::merge(body, transaction.body()),
+                None => changes.push((transaction.light_id(), transaction.body().clone())),
+            }
+        }
+
+        let mut missing = Vec::new();
+        let mut restore = LightBatch { transactions: Vec::new(), graph: None, ..*self };
+        for (light_id, body) in changes {
+            match snapshot.get(light_id) {
+                Some(state) => restore.transactions.push(LightTransaction::new(light_id.to_string(), restore_body(&body, state))),
+                None => missing.push(light_id),
+            }
+        }
+
+        tracing::warn!(failed = applied.failed().count(), lights = restore.len(), "batch failed, rolling back");
+        let mut rollback = restore.send(bridge, graph).await;
+        rollback.results.extend(missing.into_iter().map(|light_id| BatchResult {
+            light_id: light_id.to_string(),
+            result: Err(HueError::InvalidData { msg: format!("couldn't find light {} to restore", light_id) }),
+        }));
+        Ok(TransactionReport { applied, rollback: Some(rollback) })
+    }
+
+    async fn run(&self, bridge: &HueBridge, throttle: &Throttle, job: Job<'_>) -> Vec<(usize, Result<(), HueError>)> {
+        let group = match job {
+            Job::Light(index) => {
+                throttle.wait().await;
+                return vec![(index, self.transactions[index].on(bridge).await)];
+            },
+            Job::Group(group) => group,
+        };
+
+        throttle.wait().await;
+        match group.grouped_light.put(bridge, group.body).await {
+            Ok(_) => group.indices.into_iter().map(|index| (index, Ok(()))).collect(),
+            Err(err) => {
+                tracing::warn!(grouped_light = %group.grouped_light.rid, error = %err, "grouped light failed, sending per light");
+                let mut results = Vec::new();
+                for index in group.indices {
+                    throttle.wait().await;
+                    results.push((index, self.transactions[index].on(bridge).await));
+                }
+                results
+            },
+        }
+    }
+
+    // Transactions with the same body that together cover exactly the lights of a room or zone.
+    async fn find_groups(&self, bridge: &HueBridge, graph: Option<&ResourceGraph>) -> Vec<Group<'_>> {
+        let mut bodies = Vec::<(&Value, Vec<usize>)>::new();
+        for (index, transaction) in self.transactions.iter().enumerate() {
+            match bodies.iter_mut().find(|(body, _)| *body == transaction.body()) {
+                Some((_, indices)) => indices.push(index),
+                None => bodies.push((transaction.body(), vec![index])),
+            }
+        }
+        bodies.retain(|(_, indices)| indices.len() > 1);
+        if bodies.is_empty() || !matches!(bridge.api_version().await, Ok(ApiVersion::V2)) {
+            return Vec::new();
+        }
+
+        let loaded;
+        let graph = match graph {
+            Some(graph) => graph,
+            None => match ResourceGraph::load(bridge).await {
+                Ok(graph) => {
+                    loaded = graph;
+                    &loaded
+                },
+                Err(err) => {
+                    tracing::debug!(error = %err, "couldn't load groups, sending per light");
+                    return Vec::new();
+                },
+            },
+        };
+        let candidates = graph.resources("room").into_iter().chain(graph.resources("zone")).collect::<Vec<_>>();
+
+        bodies.into_iter()
+            .filter_map(|(body, indices)| {
+                let light_ids = indices.iter().map(|index| self.transactions[*index].light_id()).collect::<HashSet<_>>();
+                let grouped_light = candidates.iter().find_map(|group| {
+                    let lights = graph.lights_in(group);
+                    let covers = lights.len() == light_ids.len() && lights.iter().all(|light| light_ids.contains(light.rid.as_str()));
+                    if !covers {
+                        return None;
+                    }
+                    graph.services_of(group).iter().find(|service| service.rtype == "grouped_light").cloned()
+                })?;
+                Some(Group { grouped_light, body, indices })
+            })
+            .collect()
+    }
+}
+
+// Current state of every light, in CLIP v2 form on both api versions. With `load_graph` the whole
+// resource tree is fetched instead of only the lights, and returned as a graph on CLIP v2.
+async fn light_states(bridge: &HueBridge, load_graph: bool) -> Result<(HashMap<String, Value>, Option<ResourceGraph>), HueError> {
+    if bridge.api_version().await? == ApiVersion::V1 {
+        return Ok((v1::light_states(bridge).await?.into_iter().collect(), None));
+    }
+
+    let resources = resource::list(bridge, if load_graph { "" } else { Light::RTYPE }).await?;
+    let graph = load_graph.then(|| ResourceGraph::from_resources(&resources));
+    let states = resources.into_iter()
+        .filter(|resource| resource["type"] == Light::RTYPE)
+        .filter_map(|light| Some((light["id"].as_str()?.to_string(), light)))
+        .collect();
+    Ok((states, graph))
+}
+
+// The fields of `state` that `body` would change, e.g. `{ "dimming": { "brightness": 30 } }` for a
+// dimming change. Fields the snapshot doesn't have, or has as null, are left out.
+fn restore_body(body: &Value, state: &Value) -> Value {
+    let mut restored = restore_fields(body, state);
+
+    // A light is either in color or in white temperature mode, and whichever is sent last wins.
+    // Color changes restore the mode the light was in, with only that mode's value.
+    if let Value::Object(fields) = &mut restored {
+        if body.get("color").is_some() || body.get("color_temperature").is_some() {
+            fields.remove("color");
+            fields.remove("color_temperature");
+            let mirek = &state["color_temperature"]["mirek"];
+            if state["color_temperature"]["mirek_valid"] == true && !mirek.is_null() {
+                fields.insert("color_temperature".into(), json!({ "mirek": mirek }));
+            } else if state["color"]["xy"].is_object() {
+                fields.insert("color".into(), json!({ "xy": state["color"]["xy"] }));
+            }
+        }
+    }
+    restored
+}
+
+fn restore_fields(body: &Value, state: &Value) -> Value {
+    let Value::Object(body) = body else { return state.clone(); };
+
+    let restored = body.iter()
+        .filter(|(key, _)| !state[key.as_str()].is_null())
+        .map(|(key, value)| (key.clone(), restore_fields(value, &state[key.as_str()])))
+        .filter(|(_, value)| value.as_object().map(|fields| !fields.is_empty()).unwrap_or(true))
+        .collect::<Map<_, _>>();
+    Value::Object(restored)
+}
+
+struct Group<'a> {
+    grouped_light: ResourceRef,
+    body: &'a Value,
+    indices: Vec<usize>,
+}
+
+enum Job<'a> {
+    Light(usize),
+    Group(Group<'a>),
+}
+
+// Hands out request start times at least `interval` apart.
+struct Throttle {
+    interval: Duration,
+    next_slot: Mutex<Instant>,
+}
+
+impl Throttle {
+    async fn wait(&self) {
+        let start = {
+            let mut next_slot = self.next_slot.lock().await;
+            let start = (*next_slot).max(Instant::now());
+            *next_slot = start + self.interval;
+            start
+        };
+        tokio::time::sleep_until(start).await;
+    }
+}
+
+impl FromIterator<LightTransaction> for LightBatch {
+    fn from_iter<I: IntoIterator<Item = LightTransaction>>(transactions: I) -> Self {
+        transactions.into_iter().fold(LightBatch::new(), LightBatch::push)
+    }
+}
+
+#[derive(Debug)]
+pub struct BatchResult {
+    pub light_id: String,
+    pub result: Result<(), HueError>,
+}
+
+// One result per transaction, in the order they were added.
+#[derive(Debug)]
+pub struct BatchReport {
+    pub results: Vec<BatchResult>,
+}
+
+impl BatchReport {
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(|result| result.result.is_ok())
+    }
+
+    pub fn succeeded(&self) -> impl Iterator<Item = &str> {
+        self.results.iter().filter(|result| result.result.is_ok()).map(|result| result.light_id.as_str())
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = (&str, &HueError)> {
+        self.results.iter().filter_map(|result| Some((result.light_id.as_str(), result.result.as_ref().err()?)))
+    }
+}
+
+#[derive(Debug)]
+pub struct TransactionReport {
+    pub applied: BatchReport,
+    // The restore of every affected light, if the batch had to be rolled back.
+    pub rollback: Option<BatchReport>,
+}
+
+impl TransactionReport {
+    pub fn is_success(&self) -> bool {
+        self.applied.is_success()
+    }
+
+    pub fn rolled_back(&self) -> bool {
+        self.rollback.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::light::Light;
+    use crate::tests::memory_bridge;
+    use crate::transport::{HueResponse, MemoryTransport};
+
+    #[tokio::test]
+    async fn reports_each_light() {
+        let (bridge, transport) = memory_bridge(MemoryTransport::new()
+            .route(Method::PUT, "/clip/v2/resource/light/light-1", json!({ "errors": [], "data": [] }))
+            .route(Method::PUT, "/clip/v2/resource/light/light-3", json!({ "errors": [], "data": [] })));
+
+        let report = ["light-1", "light-2", "light-3"].into_iter()
+            .map(|light_id| Light::toggle_power_id(light_id.into(), true))
+            .collect::<LightBatch>()
+            .interval(Duration::from_millis(1))
+            .on(&bridge)
+            .await;
+
+        assert!(!report.is_success());
+        assert_eq!(report.succeeded().collect::<Vec<_>>(), vec!["light-1", "light-3"]);
+        assert_eq!(report.failed().map(|(light_id, _)| light_id).collect::<Vec<_>>(), vec!["light-2"]);
+        assert_eq!(transport.requests().iter().filter(|request| request.method == Method::PUT).count(), 3);
+    }
+
+    #[tokio::test]
+    async fn uses_grouped_light_for_whole_room() {
+        let resources = json!({ "errors": [], "data": [
+            { "id": "room-1", "type": "room", "children": [{ "rid": "device-1", "rtype": "device" }, { "rid": "device-2", "rtype": "device" }], "services": [{ "rid": "group-1", "rtype": "grouped_light" }] },
+            { "id": "device-1", "type": "device", "services": [{ "rid": "light-1", "rtype": "light" }] },
+            { "id": "device-2", "type": "device", "services": [{ "rid": "light-2", "rtype": "light" }] },
+            { "id": "device-3", "type": "device", "services": [{ "rid": "light-3", "rtype": "light" }] },
+        ]});
+        let (bridge, transport) = memory_bridge(MemoryTransport::new()
+            .route(Method::GET, "/clip/v2/resource", resources)
+            .with_fallback(|_| Ok(HueResponse::ok(json!({ "errors": [], "data": [] })))));
+
+        let report = [("light-1", true), ("light-3", false), ("light-2", true)].into_iter()
+            .map(|(light_id, on)| Light::toggle_power_id(light_id.into(), on))
+            .collect::<LightBatch>()
+            .interval(Duration::from_millis(1))
+            .on(&bridge)
+            .await;
+
+        assert_eq!(report.succeeded().collect::<Vec<_>>(), vec!["light-1", "light-3", "light-2"]);
+        let mut puts = transport.requests().iter()
+            .filter(|request| request.method == Method::PUT)
+            .map(|request| request.path().to_string())
+            .collect::<Vec<_>>();
+        puts.sort();
+        assert_eq!(puts, vec!["/clip/v2/resource/grouped_light/group-1", "/clip/v2/resource/light/light-3"]);
+    }
+
+    #[test]
+    fn restores_changed_fields() {
+        let state = json!({ "on": { "on": true }, "dimming": { "brightness": 40.0, "min_dim_level": 2.0 }, "color_temperature": { "mirek": null } });
+        let body = json!({ "on": { "on": false }, "dimming": { "brightness": 80.0 }, "color_temperature": { "mirek": 300 } });
+        assert_eq!(restore_body(&body, &state), json!({ "on": { "on": true }, "dimming": { "brightness": 40.0 } }));
+    }
+
+    #[test]
+    fn restores_color_mode() {
+        let ct_mode = json!({
+            "color": { "xy": { "x": 0.45, "y": 0.41 } },
+            "color_temperature": { "mirek": 366, "mirek_valid": true }
+        });
+        let to_red = json!({ "color": { "xy": { "x": 0.67, "y": 0.32 } } });
+        assert_eq!(restore_body(&to_red, &ct_mode), json!({ "color_temperature": { "mirek": 366 } }));
+
+        let xy_mode = json!({
+            "color": { "xy": { "x": 0.67, "y": 0.32 } },
+            "color_temperature": { "mirek": null, "mirek_valid": false }
+        });
+        let to_warm = json!({ "color_temperature": { "mirek": 400 } });
+        assert_eq!(restore_body(&to_warm, &xy_mode), json!({ "color": { "xy": { "x": 0.67, "y": 0.32 } } }));
+    }
+
+    #[tokio::test]
+    async fn rolls_back_on_failure() {
+        let lights = json!({ "errors": [], "data": [
+            { "id": "light-1", "type": "light", "on": { "on": false }, "dimming": { "brightness": 20.0 } },
+            { "id": "light-2", "type": "light", "on": { "on": false }, "dimming": { "brightness": 60.0 } },
+        ]});
+        let ok = json!({ "errors": [], "data": [] });
+        let (bridge, transport) = memory_bridge(MemoryTransport::new()
+            .route(Method::GET, "/clip/v2/resource/light", lights)
+            .route(Method::PUT, "/clip/v2/resource/light/light-1", ok.clone())
+            .route_response(Method::PUT, "/clip/v2/resource/light/light-2", HueResponse { status: 400, body: json!({ "errors": [{ "description": "device unreachable" }] }) })
+            .route(Method::PUT, "/clip/v2/resource/light/light-2", ok));
+
+        let report = LightBatch::new()
+            .push(Light::change_color_id("light-1".into(), None, Some(90.0), None))
+            .push(Light::change_color_id("light-2".into(), None, Some(90.0), None))
+            .group_lights(false)
+            .interval(Duration::from_millis(1))
+            .on_or_rollback(&bridge)
+            .await
+            .unwrap();
+
+        assert!(report.rolled_back());
+        assert_eq!(report.applied.failed().map(|(light_id, _)| light_id).collect::<Vec<_>>(), vec!["light-2"]);
+        assert!(report.rollback.unwrap().is_success());
+
+        let restored = transport.requests().into_iter()
+            .filter(|request| request.method == Method::PUT)
+            .skip(2)
+            .filter_map(|request| request.body)
+            .collect::<Vec<_>>();
+        assert_eq!(restored, vec![json!({ "dimming": { "brightness": 20.0 } }), json!({ "dimming": { "brightness": 60.0 } })]);
+    }
+
+    #[tokio::test]
+    async fn loads_resources_once() {
+        let resources = json!({ "errors": [], "data": [
+            { "id": "room-1", "type": "room", "children": [{ "rid": "device-1", "rtype": "device" }, { "rid": "device-2", "rtype": "device" }], "services": [{ "rid": "group-1", "rtype": "grouped_light" }] },
+            { "id": "device-1", "type": "device", "services": [{ "rid": "light-1", "rtype": "light" }] },
+            { "id": "device-2", "type": "device", "services": [{ "rid": "light-2", "rtype": "light" }] },
+            { "id": "light-1", "type": "light", "owner": { "rid": "device-1", "rtype": "device" }, "on": { "on": true } },
+            { "id": "light-2", "type": "light", "owner": { "rid": "device-2", "rtype": "device" }, "on": { "on": true } },
+        ]});
+        let failed = HueResponse { status: 400, body: json!({ "errors": [{ "description": "device unreachable" }] }) };
+        let (bridge, transport) = memory_bridge(MemoryTransport::new()
+            .route(Method::GET, "/clip/v2/resource", resources.clone())
+            .route_response(Method::PUT, "/clip/v2/resource/grouped_light/group-1", failed.clone())
+            .route_response(Method::PUT, "/clip/v2/resource/light/light-2", failed)
+            .with_fallback(|_| Ok(HueResponse::ok(json!({ "errors": [], "data": [] })))));
+        let batch = ["light-1", "light-2"].into_iter()
+            .map(|light_id| Light::toggle_power_id(light_id.into(), false))
+            .collect::<LightBatch>()
+            .interval(Duration::from_millis(1));
+
+        let report = batch.on_or_rollback(&bridge).await.unwrap();
+        assert!(report.rolled_back());
+        let gets = || transport.requests().iter().filter(|request| request.method == Method::GET).count();
+        assert_eq!(gets(), 1);
+
+        let batch = batch.graph(ResourceGraph::from_resources(resources["data"].as_array().unwrap()));
+        batch.on(&bridge).await;
+        assert_eq!(gets(), 1);
+    }
+
+    #[tokio::test]
+    async fn spaces_out_requests() {
+        let (bridge, _) = memory_bridge(MemoryTransport::new()
+            .with_fallback(|_| Ok(HueResponse::ok(json!({ "errors": [], "data": [] })))));
+
+        let started = Instant::now();
+        let report = (0..5)
+            .map(|light| Light::toggle_power_id(format!("light-{light}"), false))
+            .collect::<LightBatch>()
+            .group_lights(false)
+            .interval(Duration::from_millis(20))
+            .on(&bridge)
+            .await;
+
+        assert!(report.is_success());
+        assert!(started.elapsed() >= Duration::from_millis(80));
+    }
+}
@@ -0,0 +1,222 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::{HueError, HueBridge, ResourceRef};
+use crate::resource::{self, Resource};
+
+open_enum! {
+    pub enum BehaviorCategory {
+        Automation => "automation",
+        Entertainment => "entertainment",
+        Accessory => "accessory",
+        Other => "other",
+    }
+}
+
+open_enum! {
+    pub enum BehaviorStatus {
+        Initializing => "initializing",
+        Running => "running",
+        Disabled => "disabled",
+        Errored => "errored",
+    }
+}
+
+open_enum! {
+    pub enum DependeeLevel {
+        Critical => "critical",
+        NonCritical => "non_critical",
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BehaviorScriptMetadata {
+    pub name: String,
+    pub category: BehaviorCategory,
+    #[serde(flatten)]
+    pub extras: Map<String, Value>,
+}
+
+// An automation template (wake up, go to sleep, timers, ...) provided by the bridge.
+// The schemas describe what an instance of this script accepts as configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BehaviorScript {
+    pub id: String,
+    pub description: String,
+    pub version: String,
+    pub metadata: BehaviorScriptMetadata,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub configuration_schema: Value,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub trigger_schema: Value,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub state_schema: Value,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub supported_features: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_number_instances: Option<u32>,
+    #[serde(flatten)]
+    pub extras: Map<String, Value>,
+}
+
+impl BehaviorScript {
+    pub async fn list_scripts(bridge: &HueBridge) -> Result<Vec<BehaviorScript>, HueError> {
+        resource::list_as::<BehaviorScript>(bridge).await
+    }
+}
+
+impl Resource for BehaviorScript {
+    const RTYPE: &'static str = "behavior_script";
+
+    fn from_json(value: &Value) -> Option<BehaviorScript> {
+        BehaviorScript::deserialize(value).ok()
+    }
+
+    fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BehaviorInstanceMetadata {
+    pub name: String,
+    #[serde(flatten)]
+    pub extras: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BehaviorDependee {
+    pub target: ResourceRef,
+    pub level: DependeeLevel,
+    #[serde(flatten)]
+    pub extras: Map<String, Value>,
+}
+
+// A configured automation, created from a `BehaviorScript`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BehaviorInstance {
+    pub id: String,
+    pub script_id: String,
+    pub enabled: bool,
+    pub metadata: BehaviorInstanceMetadata,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub configuration: Value,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub state: Value,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependees: Vec<BehaviorDependee>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<BehaviorStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    #[serde(flatten)]
+    pub extras: Map<String, Value>,
+}
+
+impl BehaviorInstance {
+    pub async fn list_instances(bridge: &HueBridge) -> Result<Vec<BehaviorInstance>, HueError> {
+        resource::list_as::<BehaviorInstance>(bridge).await
+    }
+
+    pub async fn get_instance(bridge: &HueBridge, instance_id: &str) -> Result<BehaviorInstance, HueError> {
+        ResourceRef::new(instance_id, BehaviorInstance::RTYPE).get_as(bridge).await
+    }
+
+    pub fn create(script_id: String, name: String, configuration: Value, enabled: bool) -> BehaviorTransaction {
+        BehaviorTransaction::Create(json!({
+            "type": "behavior_instance",
+            "script_id": script_id,
+            "enabled": enabled,
+            "configuration": configuration,
+            "metadata": { "name": name }
+        }))
+    }
+
+    pub fn set_enabled(&self, enabled: bool) -> BehaviorTransaction {
+        BehaviorInstance::set_enabled_id(self.id.clone(), enabled)
+    }
+
+    pub fn set_enabled_id(instance_id: String, enabled: bool) -> BehaviorTransaction {
+        BehaviorInstance::update_id(instance_id, json!({ "enabled": enabled }))
+    }
+
+    pub fn rename(&self, name: String) -> BehaviorTransaction {
+        BehaviorInstance::update_id(self.id.clone(), json!({ "metadata": { "name": name } }))
+    }
+
+    pub fn update_configuration(&self, configuration: Value) -> BehaviorTransaction {
+        BehaviorInstance::update_id(self.id.clone(), json!({ "configuration": configuration }))
+    }
+
+    pub fn update_id(instance_id: String, body: Value) -> BehaviorTransaction {
+        BehaviorTransaction::Update(ResourceRef::new(instance_id, BehaviorInstance::RTYPE), body)
+    }
+
+    pub fn delete(&self) -> BehaviorTransaction {
+        BehaviorInstance::delete_id(self.id.clone())
+    }
+
+    pub fn delete_id(instance_id: String) -> BehaviorTransaction {
+        BehaviorTransaction::Delete(ResourceRef::new(instance_id, BehaviorInstance::RTYPE))
+    }
+}
+
+impl Resource for BehaviorInstance {
+    const RTYPE: &'static str = "behavior_instance";
+
+    fn from_json(value: &Value) -> Option<BehaviorInstance> {
+        BehaviorInstance::deserialize(value).ok()
+    }
+
+    fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+pub enum BehaviorTransaction {
+    Create(Value),
+    Update(ResourceRef, Value),
+    Delete(ResourceRef),
+}
+
+impl BehaviorTransaction {
+    // Returns the id of the behavior instance that was created, updated or deleted.
+    pub async fn on(&self, bridge: &HueBridge) -> Result<String, HueError> {
+        let changed = match self {
+            BehaviorTransaction::Create(body) => vec![resource::post(bridge, BehaviorInstance::RTYPE, body).await?],
+            BehaviorTransaction::Update(instance, body) => instance.put(bridge, body).await?,
+            BehaviorTransaction::Delete(instance) => instance.delete(bridge).await?,
+        };
+
+        changed.into_iter()
+            .next()
+            .map(|instance| instance.rid)
+            .ok_or(HueError::InvalidData { msg: "couldn't parse behavior instance rid".into() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_unknown_fields_and_values() {
+        let instance = json!({
+            "id": "instance-1",
+            "type": "behavior_instance",
+            "script_id": "script-1",
+            "enabled": true,
+            "metadata": { "name": "Wake up", "icon": "sunrise" },
+            "dependees": [{ "type": "ResourceDependee", "target": { "rid": "room-1", "rtype": "room" }, "level": "optional" }],
+            "status": "paused",
+            "migrated_from": "/sensors/12"
+        });
+
+        let parsed = BehaviorInstance::from_json(&instance).unwrap();
+        assert_eq!(parsed.status, Some(BehaviorStatus::Unknown("paused".into())));
+        assert_eq!(parsed.dependees[0].level, DependeeLevel::Unknown("optional".into()));
+        assert_eq!(parsed.extras["migrated_from"], "/sensors/12");
+        assert_eq!(parsed.to_json(), instance);
+    }
+}
@@ -0,0 +1,208 @@
+// Synchronous wrappers for callers without an async runtime, such as scripts and FFI.
+// Each bridge owns a small runtime and every call is bounded by a timeout. Don't call
+// these from inside an async context, the runtime can't block on a thread that is already
+// driving one. Dropping them there is fine.
+//
+// The wrappers have the same shape as the async api, e.g. `Light::list_lights(&bridge)` and
+// `LightTransaction::from(light::Light::toggle_power_id(light_id, true)).on(&bridge)`. Anything
+// else can be run with `HueBridge::block_on`.
+
+use std::future::Future;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::runtime::{Builder, Runtime};
+
+use crate::{ApiVersion, BridgeConfig, DiscoveredBridge, HueError};
+use crate::light;
+use crate::pairing::{PairOptions, PairProgress};
+use crate::whitelist::ApplicationKey;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn runtime() -> Result<Arc<BlockingRuntime>, HueError> {
+    let runtime = Builder::new_current_thread().enable_all().build()?;
+    Ok(Arc::new(BlockingRuntime(Some(runtime))))
+}
+
+// Dropping a tokio runtime panics inside an async context, e.g. when the last clone of a bridge
+// is dropped in a task. Shutting it down in the background doesn't.
+#[derive(Debug)]
+struct BlockingRuntime(Option<Runtime>);
+
+impl Deref for BlockingRuntime {
+    type Target = Runtime;
+
+    fn deref(&self) -> &Runtime {
+        self.0.as_ref().expect("runtime is only taken on drop")
+    }
+}
+
+impl Drop for BlockingRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+// Clones share the runtime and the underlying bridge state.
+#[derive(Debug, Clone)]
+pub struct HueBridge {
+    inner: crate::HueBridge,
+    runtime: Arc<BlockingRuntime>,
+    timeout: Duration,
+}
+
+impl HueBridge {
+    pub fn new(bridge_ip: String, username: String) -> Result<HueBridge, HueError> {
+        HueBridge::from_async(crate::HueBridge::new(bridge_ip, username))
+    }
+
+    pub fn from_async(bridge: crate::HueBridge) -> Result<HueBridge, HueError> {
+        Ok(HueBridge { inner: bridge, runtime: runtime()?, timeout: DEFAULT_TIMEOUT })
+    }
+
+    // Applies to every call made through this bridge, clone it to use a different timeout for one call.
+    pub fn with_timeout(mut self, timeout: Duration) -> HueBridge {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn as_async(&self) -> &crate::HueBridge {
+        &self.inner
+    }
+
+    pub fn into_async(self) -> crate::HueBridge {
+        self.inner
+    }
+
+    // Runs any async huey-core call on this bridge's runtime, e.g.
+    // `bridge.block_on(Room::list_rooms(bridge.as_async()))`.
+    pub fn block_on<F: Future<Output = Result<T, HueError>>, T>(&self, future: F) -> Result<T, HueError> {
+        block_on(&self.runtime, self.timeout, future)
+    }
+
+    pub fn api_version(&self) -> Result<ApiVersion, HueError> {
+        self.block_on(self.inner.api_version())
+    }
+
+    pub fn bridge_ip(&self) -> String {
+        self.inner.bridge_ip()
+    }
+
+    pub fn rediscover(&self) -> Result<bool, HueError> {
+        self.block_on(self.inner.rediscover())
+    }
+
+    pub fn application_keys(&self) -> Result<Vec<ApplicationKey>, HueError> {
+        self.block_on(self.inner.application_keys())
+    }
+
+    pub fn revoke_application_key(&self, username: &str) -> Result<(), HueError> {
+        self.block_on(self.inner.revoke_application_key(username))
+    }
+
+    pub fn fetch_config(bridge_ip: &str) -> Result<BridgeConfig, HueError> {
+        block_on(&*runtime()?, DEFAULT_TIMEOUT, crate::HueBridge::fetch_config(bridge_ip))
+    }
+
+    pub fn discover() -> Result<String, HueError> {
+        block_on(&*runtime()?, DEFAULT_TIMEOUT, crate::HueBridge::discover())
+    }
+
+    pub fn discover_bridges() -> Result<Vec<DiscoveredBridge>, HueError> {
+        block_on(&*runtime()?, DEFAULT_TIMEOUT, crate::HueBridge::discover_bridges())
+    }
+
+    pub fn pair(bridge_ip: String) -> Result<HueBridge, HueError> {
+        let runtime = runtime()?;
+        let bridge = block_on(&runtime, DEFAULT_TIMEOUT, crate::HueBridge::pair(bridge_ip))?;
+        Ok(HueBridge { inner: bridge, runtime, timeout: DEFAULT_TIMEOUT })
+    }
+
+    // Waits for the link button for up to `options.timeout`, on top of the usual call timeout.
+    pub fn pair_with(bridge_ip: String, options: PairOptions, on_progress: impl FnMut(PairProgress)) -> Result<HueBridge, HueError> {
+        let runtime = runtime()?;
+        let timeout = options.timeout + DEFAULT_TIMEOUT;
+        let bridge = block_on(&runtime, timeout, crate::HueBridge::pair_with(bridge_ip, options, on_progress))?;
+        Ok(HueBridge { inner: bridge, runtime, timeout: DEFAULT_TIMEOUT })
+    }
+}
+
+fn block_on<F: Future<Output = Result<T, HueError>>, T>(runtime: &BlockingRuntime, timeout: Duration, future: F) -> Result<T, HueError> {
+    runtime.block_on(async {
+        tokio::time::timeout(timeout, future).await.map_err(|_| HueError::Timeout)?
+    })
+}
+
+// The calls of `light::Light` that talk to the bridge, returning the usual lights.
+pub struct Light;
+
+impl Light {
+    pub fn list_lights(bridge: &HueBridge) -> Result<Vec<light::Light>, HueError> {
+        bridge.block_on(light::Light::list_lights(bridge.as_async()))
+    }
+}
+
+// A `light::LightTransaction` sent with a blocking `on`.
+#[derive(Debug)]
+pub struct LightTransaction(light::LightTransaction);
+
+impl From<light::LightTransaction> for LightTransaction {
+    fn from(transaction: light::LightTransaction) -> Self {
+        LightTransaction(transaction)
+    }
+}
+
+impl LightTransaction {
+    pub fn on(&self, bridge: &HueBridge) -> Result<(), HueError> {
+        bridge.block_on(self.0.on(bridge.as_async()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::tests::memory_bridge;
+    use crate::transport::MemoryTransport;
+
+    #[test]
+    fn blocking_calls() {
+        let lights = serde_json::from_str(include_str!("../example_json/lights.json")).unwrap();
+        let (bridge, transport) = memory_bridge(MemoryTransport::new()
+            .route(Method::GET, "/clip/v2/resource/light", lights)
+            .route(Method::PUT, "/clip/v2/resource/light/light-1", json!({ "errors": [], "data": [] })));
+        let bridge = HueBridge::from_async(bridge).unwrap();
+
+        assert_eq!(Light::list_lights(&bridge).unwrap()[0].name, "Lamp");
+        LightTransaction::from(light::Light::toggle_power_id("light-1".into(), false)).on(&bridge).unwrap();
+        assert_eq!(transport.requests()[1].body, Some(json!({ "on": { "on": false } })));
+    }
+
+    #[tokio::test]
+    async fn drops_in_async_context() {
+        let (bridge, _) = memory_bridge(MemoryTransport::new());
+        drop(HueBridge::from_async(bridge).unwrap());
+    }
+
+    #[test]
+    fn times_out() {
+        let (bridge, _) = memory_bridge(MemoryTransport::new());
+        let bridge = HueBridge::from_async(bridge).unwrap().with_timeout(Duration::from_millis(10));
+
+        let result = bridge.block_on(async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        });
+        assert!(matches!(result, Err(HueError::Timeout)));
+    }
+}
@@ -0,0 +1,128 @@
+// What a light supports, read from which features its CLIP v2 resource has. Frontends use it to
+// only show the controls that do something, e.g. no color picker for a white ambiance bulb.
+
+use std::ops::RangeInclusive;
+
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gamut {
+    pub red: [f64; 2],
+    pub green: [f64; 2],
+    pub blue: [f64; 2],
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ColorCapability {
+    // The corners of the colors the light can show, v1 bridges and some third party lights don't report them.
+    pub gamut: Option<Gamut>,
+    // "A", "B", "C" or "other".
+    pub gamut_type: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradientCapability {
+    // Most colors the light can show along its gradient at once.
+    pub points: u32,
+    pub modes: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Capabilities {
+    // Brightness in percent, `None` for lights that can only be switched on and off.
+    pub dimming: Option<RangeInclusive<f64>>,
+    // White temperature in mirek (1,000,000 / kelvin).
+    pub color_temperature: Option<RangeInclusive<u32>>,
+    pub color: Option<ColorCapability>,
+    pub gradient: Option<GradientCapability>,
+    // Effects such as "candle" or "fire", without "no_effect".
+    pub effects: Vec<String>,
+    // Effects that run for a set duration, such as "sunrise".
+    pub timed_effects: Vec<String>,
+    // Whether the light can cycle through a palette by itself, see `dynamics` in the light resource.
+    pub dynamics: bool,
+}
+
+impl Capabilities {
+    pub fn from_json(light: &Value) -> Capabilities {
+        let dimming = light["dimming"].is_object()
+            .then(|| light["dimming"]["min_dim_level"].as_f64().unwrap_or(0.0)..=100.0);
+
+        let schema = &light["color_temperature"]["mirek_schema"];
+        let color_temperature = light["color_temperature"].is_object().then(|| {
+            let min = schema["mirek_minimum"].as_u64().unwrap_or(153) as u32;
+            let max = schema["mirek_maximum"].as_u64().unwrap_or(500) as u32;
+            min..=max
+        });
+
+        let color = light["color"].is_object().then(|| ColorCapability {
+            gamut: gamut(&light["color"]["gamut"]),
+            gamut_type: light["color"]["gamut_type"].as_str().map(|gamut_type| gamut_type.into()),
+        });
+
+        let gradient = light["gradient"].is_object().then(|| GradientCapability {
+            points: light["gradient"]["points_capable"].as_u64().unwrap_or(0) as u32,
+            modes: strings(&light["gradient"]["mode_values"], ""),
+        });
+
+        Capabilities {
+            dimming,
+            color_temperature,
+            color,
+            gradient,
+            effects: strings(&light["effects"]["effect_values"], "no_effect"),
+            timed_effects: strings(&light["timed_effects"]["effect_values"], "no_effect"),
+            dynamics: !strings(&light["dynamics"]["status_values"], "none").is_empty(),
+        }
+    }
+
+    pub fn is_on_off_only(&self) -> bool {
+        self.dimming.is_none() && self.color_temperature.is_none() && self.color.is_none()
+    }
+
+    pub fn is_dimmable(&self) -> bool {
+        self.dimming.is_some()
+    }
+
+    pub fn has_color_temperature(&self) -> bool {
+        self.color_temperature.is_some()
+    }
+
+    pub fn has_color(&self) -> bool {
+        self.color.is_some()
+    }
+}
+
+fn gamut(gamut: &Value) -> Option<Gamut> {
+    let corner = |color: &str| Some([gamut[color]["x"].as_f64()?, gamut[color]["y"].as_f64()?]);
+    Some(Gamut { red: corner("red")?, green: corner("green")?, blue: corner("blue")? })
+}
+
+// The strings of a json array, leaving out the value that means "nothing".
+fn strings(values: &Value, none: &str) -> Vec<String> {
+    values.as_array()
+        .map(|values| values.iter().filter_map(|value| value.as_str()).filter(|value| *value != none).map(|value| value.into()).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn reads_capabilities() {
+        let lights = serde_json::from_str::<Value>(include_str!("../example_json/lights.json")).unwrap();
+        let lamp = Capabilities::from_json(&lights["data"][0]);
+        assert_eq!(lamp.dimming, Some(0.20000000298023224..=100.0));
+        assert_eq!(lamp.color_temperature, Some(153..=500));
+        assert_eq!(lamp.color.unwrap().gamut_type.as_deref(), Some("C"));
+        assert_eq!(lamp.effects, vec!["candle", "fire"]);
+        assert!(lamp.dynamics);
+        assert!(lamp.gradient.is_none());
+
+        let plug = Capabilities::from_json(&json!({ "id": "plug-1", "type": "light", "on": { "on": true }, "metadata": { "name": "Plug" } }));
+        assert!(plug.is_on_off_only());
+    }
+}
@@ -0,0 +1,272 @@
+// Timeouts and retries for requests to the bridge and the discovery service.
+
+use std::time::{Duration, Instant};
+
+use reqwest::Method;
+
+use crate::HueError;
+use crate::transport::{HueRequest, HueResponse, HueTransport};
+
+// Busy or restarting bridges answer with these before they are ready again.
+const RETRY_STATUS: [u16; 4] = [429, 502, 503, 504];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    // Total attempts including the first one, 1 disables retries.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    // Randomises each delay between half and the full backoff so clients don't retry in lockstep.
+    pub jitter: bool,
+    // PUTs can carry relative changes (e.g. `dimming_delta`), so they are only retried on request.
+    pub retry_put: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(4),
+            jitter: true,
+            retry_put: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> RetryPolicy {
+        RetryPolicy { max_attempts: 1, ..RetryPolicy::default() }
+    }
+
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn retry_put(mut self, retry_put: bool) -> Self {
+        self.retry_put = retry_put;
+        self
+    }
+
+    pub fn allows(&self, method: &Method) -> bool {
+        matches!(*method, Method::GET | Method::HEAD | Method::DELETE) || (self.retry_put && *method == Method::PUT)
+    }
+
+    // Delay before retry number `retry` (starting at 1), doubling each time up to `max_backoff`.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let backoff = self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_backoff);
+
+        if self.jitter {
+            backoff.mul_f64(0.5 + fastrand::f64() / 2.0)
+        } else {
+            backoff
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientConfig {
+    pub connect_timeout: Duration,
+    // Whole request including the response body, the event stream is exempt.
+    pub request_timeout: Duration,
+    pub retry: RetryPolicy,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            connect_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(10),
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+impl ClientConfig {
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    pub fn request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+}
+
+// Sends a request with the configured timeout, retrying transient failures the policy allows.
+// The last response or error is returned once the attempts run out.
+#[tracing::instrument(name = "hue_request", skip_all, fields(method = %request.method, host = request.host(), path = %request.redacted_path()))]
+pub(crate) async fn send(transport: &dyn HueTransport, config: &ClientConfig, request: HueRequest) -> Result<HueResponse, HueError> {
+    let max_attempts = if config.retry.allows(&request.method) { config.retry.max_attempts.max(1) } else { 1 };
+
+    let mut attempt = 1;
+    loop {
+        let started = Instant::now();
+        let result = match tokio::time::timeout(config.request_timeout, transport.send(request.clone())).await {
+            Ok(result) => result,
+            Err(_) => Err(HueError::Timeout),
+        };
+
+        let latency_ms = started.elapsed().as_millis() as u64;
+        match &result {
+            Ok(response) => tracing::debug!(attempt, status = response.status, latency_ms, "bridge responded"),
+            Err(err) => tracing::debug!(attempt, error = %err, latency_ms, "request failed"),
+        }
+
+        // A timed out request may still have reached the bridge, like a failed response it is only
+        // sent again for methods the policy allows, which `max_attempts` already accounts for.
+        // Other errors, such as a body that couldn't be decoded, would only fail the same way again.
+        let retryable = match &result {
+            Ok(response) => RETRY_STATUS.contains(&response.status),
+            Err(HueError::Unreachable { .. } | HueError::Timeout) => true,
+            Err(_) => false,
+        };
+        if !retryable || attempt >= max_attempts {
+            return result;
+        }
+
+        let delay = config.retry.delay(attempt);
+        tracing::warn!(attempt, max_attempts, ?delay, "retrying request");
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::transport::MemoryTransport;
+
+    fn config() -> ClientConfig {
+        ClientConfig::default().retry(RetryPolicy::default().backoff(Duration::from_millis(1), Duration::from_millis(1)))
+    }
+
+    #[test]
+    fn backoff_grows_to_max() {
+        let policy = RetryPolicy::default().jitter(false);
+        assert_eq!(policy.delay(1), Duration::from_millis(250));
+        assert_eq!(policy.delay(3), Duration::from_secs(1));
+        assert_eq!(policy.delay(10), Duration::from_secs(4));
+
+        let jittered = RetryPolicy::default().delay(2);
+        assert!(jittered >= Duration::from_millis(250) && jittered <= Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn retries_idempotent_requests() {
+        let busy = HueResponse { status: 503, body: json!({ "errors": [{ "description": "busy" }] }) };
+        let transport = MemoryTransport::new()
+            .route_response(Method::GET, "/clip/v2/resource/light", busy.clone())
+            .route(Method::GET, "/clip/v2/resource/light", json!({ "errors": [], "data": [] }))
+            .route_response(Method::PUT, "/clip/v2/resource/light/1", busy.clone())
+            .route_response(Method::PUT, "/clip/v2/resource/light/1", busy.clone())
+            .route(Method::PUT, "/clip/v2/resource/light/1", json!({ "errors": [], "data": [] }));
+
+        let get = HueRequest::new(Method::GET, "https://10.0.0.2/clip/v2/resource/light");
+        assert_eq!(send(&transport, &config(), get).await.unwrap().status, 200);
+
+        let put = HueRequest::new(Method::PUT, "https://10.0.0.2/clip/v2/resource/light/1");
+        assert_eq!(send(&transport, &config(), put.clone()).await.unwrap().status, 503);
+
+        let opted_in = config().retry(config().retry.retry_put(true));
+        assert_eq!(send(&transport, &opted_in, put).await.unwrap().status, 200);
+        assert_eq!(transport.requests().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let transport = MemoryTransport::new().with_fallback(|_| Err(HueError::Unreachable { msg: "no route to host".into() }));
+
+        let request = HueRequest::new(Method::GET, "https://10.0.0.2/api/0/config");
+        assert!(matches!(send(&transport, &config(), request).await, Err(HueError::Unreachable { .. })));
+        assert_eq!(transport.requests().len(), 3);
+    }
+
+    // Answers after a second, well past the test's request timeout.
+    #[derive(Debug, Default)]
+    struct SlowTransport {
+        requests: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl HueTransport for SlowTransport {
+        async fn send(&self, _request: HueRequest) -> Result<HueResponse, HueError> {
+            self.requests.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            Ok(HueResponse::ok(json!([])))
+        }
+
+        async fn stream(&self, _request: HueRequest) -> Result<crate::transport::ByteStream, HueError> {
+            Err(HueError::Unsupported)
+        }
+    }
+
+    // Answers every connection with a body that isn't json.
+    fn malformed_server() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/api/test-key/lights", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        std::thread::spawn(move || {
+            for mut socket in listener.incoming().flatten() {
+                counter.fetch_add(1, Ordering::SeqCst);
+                // Reads up to the blank line after the headers, the requests have no body.
+                let request = BufReader::new(&socket).lines().map_while(Result::ok).take_while(|line| !line.is_empty()).count();
+                assert!(request > 0);
+                socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 1\r\nConnection: close\r\n\r\n{").ok();
+            }
+        });
+        (url, connections)
+    }
+
+    #[tokio::test]
+    async fn malformed_body_not_retried() {
+        let (url, connections) = malformed_server();
+        let transport = crate::transport::ReqwestTransport::new().unwrap();
+
+        let result = send(&transport, &config(), HueRequest::new(Method::GET, url)).await;
+        assert!(matches!(result, Err(HueError::Request(_))));
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn times_out() {
+        let transport = SlowTransport::default();
+        let config = config().request_timeout(Duration::from_millis(10));
+
+        let post = HueRequest::new(Method::POST, "https://10.0.0.2/api/test-key/lights/1/state");
+        assert!(matches!(send(&transport, &config, post).await, Err(HueError::Timeout)));
+        assert_eq!(transport.requests.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let get = HueRequest::new(Method::GET, "https://10.0.0.2/api/test-key/lights");
+        assert!(matches!(send(&transport, &config, get).await, Err(HueError::Timeout)));
+        assert_eq!(transport.requests.load(std::sync::atomic::Ordering::SeqCst), 4);
+    }
+}
@@ -0,0 +1,120 @@
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::{HueError, HueBridge, ResourceRef};
+use crate::resource::{self, Resource};
+
+#[derive(Debug, Clone, Deserialize)]
+struct DeviceData {
+    id: String,
+    metadata: DeviceMetadata,
+    #[serde(default)]
+    services: Vec<ResourceRef>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DeviceMetadata {
+    name: String,
+    archetype: String,
+}
+
+// A physical product such as a bulb, switch or the bridge itself. The Hue apps show the
+// device name for its lights, so renaming the device is what users usually expect.
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub id: String,
+    pub name: String,
+    pub archetype: String,
+    // Lights, buttons, sensors and so on provided by the device.
+    pub services: Vec<ResourceRef>,
+    // Fields not covered above, such as the product data.
+    pub extras: Map<String, Value>,
+}
+
+impl Device {
+    // CLIP v2 only.
+    pub async fn list_devices(bridge: &HueBridge) -> Result<Vec<Device>, HueError> {
+        resource::list_as::<Device>(bridge).await
+    }
+
+    pub fn rename(&self, name: impl Into<String>) -> DeviceTransaction {
+        Device::rename_id(self.id.clone(), name)
+    }
+
+    pub fn rename_id(device_id: String, name: impl Into<String>) -> DeviceTransaction {
+        DeviceTransaction { device_id, body: json!({ "metadata": { "name": name.into() } }) }
+    }
+
+    pub fn set_archetype_id(device_id: String, archetype: impl Into<String>) -> DeviceTransaction {
+        DeviceTransaction { device_id, body: json!({ "metadata": { "archetype": archetype.into() } }) }
+    }
+}
+
+impl Resource for Device {
+    const RTYPE: &'static str = "device";
+
+    fn from_json(value: &Value) -> Option<Device> {
+        let device = DeviceData::deserialize(value).ok()?;
+        let mut device = Device {
+            id: device.id,
+            name: device.metadata.name,
+            archetype: device.metadata.archetype,
+            services: device.services,
+            extras: Map::new(),
+        };
+        device.extras = resource::unknown_fields(value, &device.to_json());
+        Some(device)
+    }
+
+    fn to_json(&self) -> Value {
+        let known = json!({
+            "id": self.id,
+            "type": Device::RTYPE,
+            "metadata": { "name": self.name, "archetype": self.archetype },
+            "services": self.services
+        });
+        resource::with_extras(known, &self.extras)
+    }
+}
+
+#[derive(Debug)]
+pub struct DeviceTransaction {
+    device_id: String,
+    body: Value
+}
+
+impl DeviceTransaction {
+    pub async fn on(&self, bridge: &HueBridge) -> Result<(), HueError> {
+        ResourceRef::new(self.device_id.clone(), Device::RTYPE).put(bridge, &self.body).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Method;
+
+    use super::*;
+    use crate::tests::memory_bridge;
+    use crate::transport::MemoryTransport;
+
+    #[tokio::test]
+    async fn renames_device() {
+        let device = json!({
+            "id": "device-1",
+            "type": "device",
+            "metadata": { "name": "Hue color lamp 1", "archetype": "sultan_bulb" },
+            "product_data": { "model_id": "LCA001" },
+            "services": [{ "rid": "light-1", "rtype": "light" }]
+        });
+        let (bridge, transport) = memory_bridge(MemoryTransport::new()
+            .route(Method::GET, "/clip/v2/resource/device", json!({ "errors": [], "data": [device] }))
+            .route(Method::PUT, "/clip/v2/resource/device/device-1", json!({ "errors": [], "data": [{ "rid": "device-1", "rtype": "device" }] })));
+
+        let device = Device::list_devices(&bridge).await.unwrap().remove(0);
+        assert_eq!(device.extras["product_data"]["model_id"], "LCA001");
+
+        device.rename("Reading lamp").on(&bridge).await.unwrap();
+        assert_eq!(transport.requests()[1].body, Some(json!({ "metadata": { "name": "Reading lamp" } })));
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{BridgeEvent, HueError, HueBridge};
+use crate::config::ClientConfig;
+use crate::store::normalize_id;
+use crate::transport::{default_transport, HueTransport};
+
+impl HueBridge {
+    // Finds the current ip of a bridge by id, trying mDNS on the local network before the cloud discovery service.
+    pub async fn locate(bridge_id: &str) -> Result<Option<String>, HueError> {
+        let config = ClientConfig::default();
+        HueBridge::locate_with(&*default_transport(&config), &config, bridge_id).await
+    }
+
+    #[tracing::instrument(name = "locate", skip(transport, config))]
+    pub async fn locate_with(transport: &dyn HueTransport, config: &ClientConfig, bridge_id: &str) -> Result<Option<String>, HueError> {
+        let bridge_id = normalize_id(bridge_id);
+
+        #[cfg(feature = "mdns")]
+        if let Ok(bridges) = mdns::discover(std::time::Duration::from_secs(3)).await {
+            if let Some(bridge) = bridges.into_iter().find(|bridge| normalize_id(&bridge.id) == bridge_id) {
+                tracing::debug!(bridge_ip = %bridge.internal_ip_address, "found over mdns");
+                return Ok(Some(bridge.internal_ip_address));
+            }
+        }
+
+        let bridge_ip = HueBridge::discover_bridges_with(transport, config)
+            .await?
+            .into_iter()
+            .find(|bridge| normalize_id(&bridge.id) == bridge_id)
+            .map(|bridge| bridge.internal_ip_address);
+
+        tracing::debug!(bridge_ip = bridge_ip.as_deref(), "cloud discovery finished");
+        Ok(bridge_ip)
+    }
+
+    // Looks the bridge up by id and switches to its new ip if it moved.
+    // Returns whether the ip changed, bridges without a known id can't be rediscovered.
+    #[tracing::instrument(skip_all, fields(bridge_id = self.bridge_id.as_deref()))]
+    pub async fn rediscover(&self) -> Result<bool, HueError> {
+        let Some(bridge_id) = &self.bridge_id else { return Ok(false); };
+        let Some(new_ip) = HueBridge::locate_with(&*self.transport, &self.config, bridge_id).await? else { return Ok(false); };
+
+        let old_ip = self.bridge_ip();
+        if new_ip == old_ip {
+            return Ok(false);
+        }
+
+        tracing::info!(%old_ip, %new_ip, "bridge moved");
+        self.set_bridge_ip(new_ip.clone());
+        self.emit(BridgeEvent::IpChanged { bridge_id: bridge_id.clone(), old_ip, new_ip });
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "mdns")]
+pub mod mdns {
+    use std::time::{Duration, Instant};
+
+    use mdns_sd::{ServiceDaemon, ServiceEvent};
+
+    use crate::{DiscoveredBridge, HueError};
+
+    const HUE_SERVICE: &str = "_hue._tcp.local.";
+
+    // Browses the local network for bridges advertising themselves over mDNS for `timeout`.
+    #[tracing::instrument(name = "mdns_discover")]
+    pub async fn discover(timeout: Duration) -> Result<Vec<DiscoveredBridge>, HueError> {
+        let mdns_error = |err: mdns_sd::Error| HueError::InvalidData { msg: format!("mdns discovery failed: {}", err) };
+        let daemon = ServiceDaemon::new().map_err(mdns_error)?;
+        let receiver = daemon.browse(HUE_SERVICE).map_err(mdns_error)?;
+
+        let mut bridges: Vec<DiscoveredBridge> = Vec::new();
+        let started = Instant::now();
+        while started.elapsed() < timeout {
+            let Ok(event) = receiver.try_recv() else {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            };
+
+            let ServiceEvent::ServiceResolved(info) = event else { continue; };
+            let Some(bridge_id) = info.get_property_val_str("bridgeid") else { continue; };
+            let Some(address) = info.get_addresses_v4().into_iter().next().copied() else { continue; };
+
+            if !bridges.iter().any(|bridge| bridge.id.eq_ignore_ascii_case(bridge_id)) {
+                bridges.push(DiscoveredBridge {
+                    id: bridge_id.to_lowercase(),
+                    internal_ip_address: address.to_string(),
+                    port: Some(info.get_port()),
+                });
+            }
+        }
+
+        daemon.shutdown().ok();
+        tracing::debug!(found = bridges.len(), "mdns discovery finished");
+        Ok(bridges)
+    }
+}
@@ -0,0 +1,180 @@
+// Index of the references between CLIP v2 resources. Services (lights, sensors, ...) are owned
+// by a device, devices are children of a room, and rooms are children of the bridge home.
+// Zones are the exception and list light services as children directly.
+
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
+
+use crate::{HueError, HueBridge, ResourceRef};
+use crate::resource;
+
+#[derive(Debug, Clone, Default)]
+pub struct ResourceGraph {
+    rtypes: HashMap<String, String>,
+    owners: HashMap<String, ResourceRef>,
+    children: HashMap<String, Vec<ResourceRef>>,
+    parents: HashMap<String, Vec<ResourceRef>>,
+    services: HashMap<String, Vec<ResourceRef>>,
+}
+
+impl ResourceGraph {
+    // Builds the graph from a single fetch of every resource on the bridge.
+    pub async fn load(bridge: &HueBridge) -> Result<ResourceGraph, HueError> {
+        let resources = resource::list(bridge, "").await?;
+        Ok(ResourceGraph::from_resources(&resources))
+    }
+
+    pub fn from_resources<'a>(resources: impl IntoIterator<Item = &'a Value>) -> ResourceGraph {
+        let mut graph = ResourceGraph::default();
+        for resource in resources {
+            let (Some(rid), Some(rtype)) = (resource["id"].as_str(), resource["type"].as_str()) else { continue; };
+            graph.rtypes.insert(rid.into(), rtype.into());
+
+            if let Ok(owner) = serde_json::from_value::<ResourceRef>(resource["owner"].clone()) {
+                graph.owners.insert(rid.into(), owner);
+            }
+            let references = |field: &str| serde_json::from_value::<Vec<ResourceRef>>(resource[field].clone()).unwrap_or_default();
+            let children = references("children");
+            for child in &children {
+                graph.parents.entry(child.rid.clone()).or_default().push(ResourceRef::new(rid, rtype));
+            }
+            graph.children.insert(rid.into(), children);
+            graph.services.insert(rid.into(), references("services"));
+        }
+        graph
+    }
+
+    // The full reference for a resource id, if it exists.
+    pub fn reference(&self, rid: &str) -> Option<ResourceRef> {
+        self.rtypes.get(rid).map(|rtype| ResourceRef::new(rid, rtype.clone()))
+    }
+
+    pub fn resources(&self, rtype: &str) -> Vec<ResourceRef> {
+        self.rtypes.iter()
+            .filter(|(_, resource_type)| *resource_type == rtype)
+            .map(|(rid, rtype)| ResourceRef::new(rid.clone(), rtype.clone()))
+            .collect()
+    }
+
+    pub fn owner_of(&self, resource: &ResourceRef) -> Option<&ResourceRef> {
+        self.owners.get(&resource.rid)
+    }
+
+    // The device a service belongs to, a device is its own device.
+    pub fn device_of(&self, resource: &ResourceRef) -> Option<ResourceRef> {
+        if resource.rtype == "device" {
+            return self.reference(&resource.rid);
+        }
+        self.owner_of(resource).filter(|owner| owner.rtype == "device").cloned()
+    }
+
+    pub fn services_of(&self, device: &ResourceRef) -> &[ResourceRef] {
+        self.services.get(&device.rid).map(|services| services.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn children_of(&self, group: &ResourceRef) -> &[ResourceRef] {
+        self.children.get(&group.rid).map(|children| children.as_slice()).unwrap_or(&[])
+    }
+
+    // Rooms, zones and the bridge home that list the resource as a child.
+    pub fn parents_of(&self, resource: &ResourceRef) -> &[ResourceRef] {
+        self.parents.get(&resource.rid).map(|parents| parents.as_slice()).unwrap_or(&[])
+    }
+
+    // Rooms hold devices, so services are looked up through their device.
+    pub fn room_of(&self, resource: &ResourceRef) -> Option<ResourceRef> {
+        let device = self.device_of(resource)?;
+        self.parents_of(&device).iter().find(|parent| parent.rtype == "room").cloned()
+    }
+
+    // A light can be in any number of zones.
+    pub fn zones_of(&self, resource: &ResourceRef) -> Vec<ResourceRef> {
+        let mut candidates = vec![resource.clone()];
+        if let Some(device) = self.device_of(resource) {
+            candidates.extend(self.services_of(&device).iter().filter(|service| service.rtype == "light").cloned());
+        }
+
+        let mut zones = Vec::new();
+        for candidate in candidates {
+            for parent in self.parents_of(&candidate).iter().filter(|parent| parent.rtype == "zone") {
+                if !zones.contains(parent) {
+                    zones.push(parent.clone());
+                }
+            }
+        }
+        zones
+    }
+
+    // Every light in a room, zone or the bridge home, following devices and nested groups.
+    pub fn lights_in(&self, group: &ResourceRef) -> Vec<ResourceRef> {
+        let mut lights = Vec::new();
+        let mut visited = HashSet::new();
+        self.collect_lights(group, &mut lights, &mut visited);
+        lights
+    }
+
+    fn collect_lights(&self, resource: &ResourceRef, lights: &mut Vec<ResourceRef>, visited: &mut HashSet<String>) {
+        if !visited.insert(resource.rid.clone()) {
+            return;
+        }
+
+        match resource.rtype.as_str() {
+            "light" => lights.push(resource.clone()),
+            "device" => {
+                for service in self.services_of(resource).iter().filter(|service| service.rtype == "light") {
+                    self.collect_lights(service, lights, visited);
+                }
+            },
+            _ => {
+                for child in self.children_of(resource) {
+                    self.collect_lights(child, lights, visited);
+                }
+            },
+        }
+    }
+
+    // The bridge resource, owned by the bridge's own device.
+    pub fn bridge(&self) -> Option<ResourceRef> {
+        self.resources("bridge").into_iter().next()
+    }
+
+    pub fn bridge_home(&self) -> Option<ResourceRef> {
+        self.resources("bridge_home").into_iter().next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn graph() -> ResourceGraph {
+        let resources = [
+            json!({ "id": "home", "type": "bridge_home", "children": [{ "rid": "room-1", "rtype": "room" }], "services": [] }),
+            json!({ "id": "room-1", "type": "room", "children": [{ "rid": "device-1", "rtype": "device" }], "services": [{ "rid": "group-1", "rtype": "grouped_light" }] }),
+            json!({ "id": "zone-1", "type": "zone", "children": [{ "rid": "light-1", "rtype": "light" }], "services": [] }),
+            json!({ "id": "device-1", "type": "device", "services": [{ "rid": "light-1", "rtype": "light" }, { "rid": "zigbee-1", "rtype": "zigbee_connectivity" }] }),
+            json!({ "id": "light-1", "type": "light", "owner": { "rid": "device-1", "rtype": "device" } }),
+            json!({ "id": "bridge-1", "type": "bridge", "owner": { "rid": "device-0", "rtype": "device" } }),
+        ];
+        ResourceGraph::from_resources(&resources)
+    }
+
+    #[test]
+    fn navigates_references() {
+        let graph = graph();
+        let light = graph.reference("light-1").unwrap();
+        let room = ResourceRef::new("room-1", "room");
+        let device = ResourceRef::new("device-1", "device");
+
+        assert_eq!(graph.device_of(&light), Some(device.clone()));
+        assert_eq!(graph.services_of(&device).len(), 2);
+        assert_eq!(graph.room_of(&light), Some(room.clone()));
+        assert_eq!(graph.zones_of(&device), vec![ResourceRef::new("zone-1", "zone")]);
+        assert_eq!(graph.lights_in(&room), vec![light.clone()]);
+        assert_eq!(graph.lights_in(&graph.bridge_home().unwrap()), vec![light]);
+        assert_eq!(graph.bridge(), Some(ResourceRef::new("bridge-1", "bridge")));
+    }
+}
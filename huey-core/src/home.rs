@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+
+use futures::future::join_all;
+
+use crate::{HueError, HueBridge};
+use crate::light::{Light, LightTransaction};
+use crate::room::Room;
+use crate::scene::Scene;
+use crate::store::{normalize_id, CredentialStore};
+
+// A resource id made unique across bridges, written as "<bridge id>/<rid>".
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct QualifiedId {
+    pub bridge_id: String,
+    pub rid: String,
+}
+
+impl QualifiedId {
+    pub fn new(bridge_id: &str, rid: impl Into<String>) -> QualifiedId {
+        QualifiedId { bridge_id: normalize_id(bridge_id), rid: rid.into() }
+    }
+}
+
+impl fmt::Display for QualifiedId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.bridge_id, self.rid)
+    }
+}
+
+impl FromStr for QualifiedId {
+    type Err = HueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (bridge_id, rid) = s.split_once('/')
+            .filter(|(bridge_id, rid)| !bridge_id.is_empty() && !rid.is_empty())
+            .ok_or(HueError::InvalidData { msg: format!("expected <bridge id>/<rid>, got {}", s) })?;
+        Ok(QualifiedId::new(bridge_id, rid))
+    }
+}
+
+// A resource listed from one bridge of a `HueHome`.
+#[derive(Debug, Clone)]
+pub struct Qualified<T> {
+    pub id: QualifiedId,
+    pub resource: T,
+}
+
+// The resources of every bridge that could be listed, next to the bridges that couldn't.
+#[derive(Debug)]
+pub struct HomeListing<T> {
+    pub resources: Vec<Qualified<T>>,
+    // Bridge id and error of each bridge that failed, e.g. because it's switched off.
+    pub failed: Vec<(String, HueError)>,
+}
+
+// Several paired bridges treated as one system. Resources are listed from every bridge
+// with bridge-qualified ids and commands are routed to the bridge that owns the resource.
+#[derive(Debug, Clone, Default)]
+pub struct HueHome {
+    bridges: BTreeMap<String, HueBridge>,
+}
+
+impl HueHome {
+    pub fn new() -> HueHome {
+        HueHome::default()
+    }
+
+    // Every bridge in the credential store.
+    pub fn from_store(store: &CredentialStore) -> Result<HueHome, HueError> {
+        let mut home = HueHome::new();
+        for (bridge_id, _) in store.bridges() {
+            if let Some(bridge) = store.bridge(bridge_id)? {
+                home.add(bridge)?;
+            }
+        }
+        Ok(home)
+    }
+
+    pub fn add(&mut self, bridge: HueBridge) -> Result<(), HueError> {
+        let bridge_id = bridge.bridge_id.as_deref()
+            .map(normalize_id)
+            .ok_or(HueError::InvalidData { msg: "bridge id is required to add a bridge to a home".into() })?;
+
+        self.bridges.insert(bridge_id, bridge);
+        Ok(())
+    }
+
+    pub fn bridges(&self) -> impl Iterator<Item = (&String, &HueBridge)> {
+        self.bridges.iter()
+    }
+
+    pub fn bridge(&self, bridge_id: &str) -> Result<&HueBridge, HueError> {
+        self.bridges.get(&normalize_id(bridge_id))
+            .ok_or(HueError::InvalidData { msg: format!("unknown bridge {}", bridge_id) })
+    }
+
+    pub async fn lights(&self) -> HomeListing<Light> {
+        self.collect(Light::list_lights, |light| light.id.clone()).await
+    }
+
+    pub async fn rooms(&self) -> HomeListing<Room> {
+        self.collect(Room::list_rooms, |room| room.id.clone()).await
+    }
+
+    pub async fn zones(&self) -> HomeListing<Room> {
+        self.collect(Room::list_zones, |zone| zone.id.clone()).await
+    }
+
+    pub async fn scenes(&self) -> HomeListing<Scene> {
+        self.collect(Scene::list_scenes, |scene| scene.id.clone()).await
+    }
+
+    // Builds a transaction for the light's local id and sends it to the bridge that owns it, e.g.
+    // `home.light_transaction(&id, |light_id| Light::toggle_power_id(light_id, true))`.
+    pub async fn light_transaction(&self, id: &QualifiedId, build: impl FnOnce(String) -> LightTransaction) -> Result<(), HueError> {
+        let bridge = self.bridge(&id.bridge_id)?;
+        build(id.rid.clone()).on(bridge).await
+    }
+
+    pub async fn recall_scene(&self, id: &QualifiedId) -> Result<(), HueError> {
+        let bridge = self.bridge(&id.bridge_id)?;
+        Scene::recall_id(id.rid.clone()).on(bridge).await
+    }
+
+    // Lists a resource from every bridge concurrently, one unreachable bridge doesn't hide the others.
+    async fn collect<'a, T, F, Fut>(&'a self, list: F, rid: impl Fn(&T) -> String) -> HomeListing<T>
+    where
+        F: Fn(&'a HueBridge) -> Fut,
+        Fut: Future<Output = Result<Vec<T>, HueError>>,
+    {
+        let per_bridge = join_all(self.bridges.iter().map(|(bridge_id, bridge)| {
+            let listing = list(bridge);
+            async move { (bridge_id, listing.await) }
+        })).await;
+
+        let mut listing = HomeListing { resources: Vec::new(), failed: Vec::new() };
+        for (bridge_id, result) in per_bridge {
+            match result {
+                Ok(resources) => listing.resources.extend(resources.into_iter().map(|resource| Qualified {
+                    id: QualifiedId::new(bridge_id, rid(&resource)),
+                    resource,
+                })),
+                Err(err) => {
+                    tracing::warn!(%bridge_id, error = %err, "couldn't list bridge");
+                    listing.failed.push((bridge_id.clone(), err));
+                },
+            }
+        }
+        listing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use reqwest::Method;
+
+    use super::*;
+    use crate::ApiVersion;
+    use crate::transport::MemoryTransport;
+
+    #[test]
+    fn qualified_id_round_trip() {
+        let id: QualifiedId = "ECB5FAFFFE000001/afafbcfd-0807-49bc-aa72-289f5ffe4005".parse().unwrap();
+        assert_eq!(id.bridge_id, "ecb5fafffe000001");
+        assert_eq!(id.rid, "afafbcfd-0807-49bc-aa72-289f5ffe4005");
+        assert_eq!(id.to_string(), "ecb5fafffe000001/afafbcfd-0807-49bc-aa72-289f5ffe4005");
+
+        assert!("no-separator".parse::<QualifiedId>().is_err());
+        assert!("/rid".parse::<QualifiedId>().is_err());
+    }
+
+    #[tokio::test]
+    async fn lists_reachable_bridges() {
+        let lights = serde_json::from_str(include_str!("../example_json/lights.json")).unwrap();
+        let reachable = Arc::new(MemoryTransport::new().route(Method::GET, "/clip/v2/resource/light", lights));
+        let unreachable = Arc::new(MemoryTransport::new().with_fallback(|request| Err(HueError::Unreachable { msg: request.url.clone() })));
+
+        let mut home = HueHome::new();
+        for (bridge_id, transport) in [("ecb5fafffe000001", reachable), ("ecb5fafffe000002", unreachable)] {
+            home.add(HueBridge::new("10.0.0.2".into(), "test-key".into())
+                .with_api_version(ApiVersion::V2)
+                .with_bridge_id(bridge_id.into())
+                .with_transport(transport)).unwrap();
+        }
+
+        let listing = home.lights().await;
+        assert!(!listing.resources.is_empty());
+        assert!(listing.resources.iter().all(|light| light.id.bridge_id == "ecb5fafffe000001"));
+        assert_eq!(listing.failed.len(), 1);
+        assert_eq!(listing.failed[0].0, "ecb5fafffe000002");
+    }
+}
@@ -1,10 +1,39 @@
-use std::collections::HashMap;
-use reqwest::ClientBuilder;
+use std::sync::{Arc, RwLock};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
 use serde_json::{ Map, Value, json };
 use uuid::Uuid;
 use thiserror::Error;
+use config::ClientConfig;
+use transport::{default_transport, ByteStream, DryRunTransport, HueRequest, HueTransport};
 
+#[macro_use]
+mod macros;
+
+pub mod batch;
+pub mod behavior;
+pub mod config;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod capabilities;
+pub mod device;
+pub mod discovery;
+pub mod fixture;
+pub mod graph;
+pub mod home;
 pub mod light;
+pub mod pairing;
+pub mod powerup;
+pub mod resource;
+pub mod room;
+pub mod scene;
+pub mod secret;
+pub mod sensor;
+pub mod state;
+pub mod store;
+pub mod transport;
+pub mod v1;
+pub mod whitelist;
 
 #[derive(Error, Debug)]
 pub enum HueError {
@@ -14,71 +43,308 @@ pub enum HueError {
     Parsing(#[from] serde_json::Error),
     #[error("Link button has not been pressed.")]
     LinkButtonNotPressed,
+    #[error("Link button was not pressed before the pairing timeout.")]
+    PairTimeout,
     #[error("Invalid json ({msg:?})")]
     InvalidData {
         msg: String
     },
     #[error("Unable to create reqwest client.")]
     ClientCreate,
+    #[error("Unable to reach host ({msg:?})")]
+    Unreachable {
+        msg: String
+    },
+    #[error("Bridge returned an error ({msg:?})")]
+    Bridge {
+        msg: String
+    },
+    #[error("Error accessing credential store")]
+    Store(#[from] std::io::Error),
+    #[error("Unable to locate config directory.")]
+    ConfigDir,
+    #[error("Not supported by this bridge's API version.")]
+    Unsupported,
+    #[error("Unable to access stored key ({msg:?})")]
+    SecretStore {
+        msg: String
+    },
+    #[error("Request timed out.")]
+    Timeout,
+    #[error("Invalid room or zone membership ({msg:?})")]
+    InvalidMembership {
+        msg: String
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DiscoveredBridge {
+    pub id: String,
+    #[serde(rename = "internalipaddress")]
+    pub internal_ip_address: String,
+    pub port: Option<u16>,
+}
+
+// Unauthenticated subset of the bridge config, available from /api/0/config.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BridgeConfig {
+    pub name: String,
+    #[serde(rename = "bridgeid")]
+    pub bridge_id: String,
+    #[serde(rename = "apiversion")]
+    pub api_version: String,
+    #[serde(rename = "swversion")]
+    pub sw_version: String,
+    #[serde(rename = "modelid")]
+    pub model_id: String,
+}
+
+// Reference from one CLIP v2 resource to another, e.g. a light's owner device.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ResourceRef {
+    pub rid: String,
+    pub rtype: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    // Legacy /api/<username>/... API, the only one on first generation bridges and old firmware.
+    V1,
+    // CLIP v2, /clip/v2/resource/...
+    V2,
+}
+
+impl ApiVersion {
+    // CLIP v2 shipped with bridge API version 1.46.
+    pub fn from_api_version(api_version: &str) -> ApiVersion {
+        let mut parts = api_version.split('.').map(|part| part.parse::<u32>().unwrap_or(0));
+        let major = parts.next().unwrap_or(0);
+        let minor = parts.next().unwrap_or(0);
+        if (major, minor) >= (1, 46) { ApiVersion::V2 } else { ApiVersion::V1 }
+    }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BridgeEvent {
+    // The bridge was found again at a new address after a connection error.
+    IpChanged { bridge_id: String, old_ip: String, new_ip: String },
+}
+
+// Clones share the bridge ip and event channel, so a rediscovered ip is picked up by every clone.
 #[derive(Debug, Clone)]
 pub struct HueBridge {
-    pub bridge_ip: String,
-    pub username: String
+    bridge_ip: Arc<RwLock<String>>,
+    pub username: String,
+    pub client_key: Option<String>,
+    pub bridge_id: Option<String>,
+    api_version: Arc<RwLock<Option<ApiVersion>>>,
+    events: tokio::sync::broadcast::Sender<BridgeEvent>,
+    transport: Arc<dyn HueTransport>,
+    // Set by `with_transport`, otherwise the transport is rebuilt when the config changes.
+    custom_transport: bool,
+    config: ClientConfig,
 }
 
 impl HueBridge {
     pub fn new(bridge_ip: String, username: String) -> HueBridge {
-        HueBridge { bridge_ip, username }
+        let (events, _) = tokio::sync::broadcast::channel(16);
+        HueBridge {
+            bridge_ip: Arc::new(RwLock::new(bridge_ip)),
+            username,
+            client_key: None,
+            bridge_id: None,
+            api_version: Arc::new(RwLock::new(None)),
+            events,
+            transport: default_transport(&ClientConfig::default()),
+            custom_transport: false,
+            config: ClientConfig::default(),
+        }
+    }
+
+    pub fn with_transport(mut self, transport: Arc<dyn HueTransport>) -> HueBridge {
+        self.transport = transport;
+        self.custom_transport = true;
+        self
     }
 
-    pub fn build_client(&self) -> Result<reqwest::Client, HueError> {
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert("hue-application-key", self.username.parse().unwrap());
+    pub fn transport(&self) -> &Arc<dyn HueTransport> {
+        &self.transport
+    }
 
-        let client = ClientBuilder::new()
-            .danger_accept_invalid_certs(true)
-            .default_headers(headers)
-            .build()
-            .map_err(|_| { HueError::ClientCreate })?;
+    // A bridge that reads as usual but only records changes, see `DryRunTransport`. It shares the
+    // ip and event channel with this one, so both can be used side by side.
+    pub fn dry_run(&self) -> (HueBridge, Arc<DryRunTransport>) {
+        let transport = Arc::new(DryRunTransport::new(self.transport.clone()));
+        (self.clone().with_transport(transport.clone()), transport)
+    }
 
-        Ok(client)
+    // Timeouts and retries for this bridge. The default transport is rebuilt to use the connect
+    // timeout, a transport set with `with_transport` is kept as is.
+    pub fn with_config(mut self, config: ClientConfig) -> HueBridge {
+        if !self.custom_transport {
+            self.transport = default_transport(&config);
+        }
+        self.config = config;
+        self
     }
 
-    pub async fn discover() -> Result::<String, HueError> {
-        let req = format!("https://discovery.meethue.com/");
+    pub fn config(&self) -> &ClientConfig {
+        &self.config
+    }
 
-        let response = reqwest::get(req)
-            .await?
-            .json::<Value>() // Gives back a json array of a single result
-            .await?;
+    // Skips detecting the API version from the bridge config.
+    pub fn with_api_version(self, api_version: ApiVersion) -> HueBridge {
+        *self.api_version.write().unwrap() = Some(api_version);
+        self
+    }
 
-        let bridges = serde_json::from_value::<Vec<HashMap<String, Value>>>(response)?
+    // Detected from the bridge config on first use and cached for every clone.
+    pub async fn api_version(&self) -> Result<ApiVersion, HueError> {
+        if let Some(api_version) = *self.api_version.read().unwrap() {
+            return Ok(api_version);
+        }
+
+        let config = HueBridge::fetch_config_with(&*self.transport, &self.config, &self.bridge_ip()).await?;
+        let api_version = ApiVersion::from_api_version(&config.api_version);
+        *self.api_version.write().unwrap() = Some(api_version);
+        Ok(api_version)
+    }
+
+    pub fn bridge_ip(&self) -> String {
+        self.bridge_ip.read().unwrap().clone()
+    }
+
+    pub fn set_bridge_ip(&self, bridge_ip: String) {
+        *self.bridge_ip.write().unwrap() = bridge_ip;
+    }
+
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<BridgeEvent> {
+        self.events.subscribe()
+    }
+
+    pub(crate) fn emit(&self, event: BridgeEvent) {
+        // No subscribers is fine, the event is only informational.
+        self.events.send(event).ok();
+    }
+
+    pub fn with_bridge_id(mut self, bridge_id: String) -> HueBridge {
+        self.bridge_id = Some(bridge_id);
+        self
+    }
+
+    pub fn with_client_key(mut self, client_key: String) -> HueBridge {
+        self.client_key = Some(client_key);
+        self
+    }
+
+    // Sends a request to the bridge, if the bridge can't be reached it is rediscovered by id and the request retried once.
+    pub(crate) async fn request(&self, method: Method, path: &str, body: Option<&Value>) -> Result<Value, HueError> {
+        if path.starts_with("/clip/") && *self.api_version.read().unwrap() == Some(ApiVersion::V1) {
+            return Err(HueError::Unsupported);
+        }
+
+        match self.send(method.clone(), path, body).await {
+            Err(err @ (HueError::Unreachable { .. } | HueError::Timeout)) => {
+                // Unreachable means the connection failed and nothing was sent. A timed out request
+                // may have reached the bridge, so it's only sent again if the retry policy allows it.
+                let resend = matches!(err, HueError::Unreachable { .. }) || self.config.retry.allows(&method);
+                match self.rediscover().await {
+                    Ok(true) if resend => self.send(method, path, body).await,
+                    _ => Err(err),
+                }
+            },
+            result => result,
+        }
+    }
+
+    // First generation bridges only serve the v1 api, and only over plain http.
+    fn url(&self, path: &str) -> String {
+        let scheme = match *self.api_version.read().unwrap() {
+            Some(ApiVersion::V1) => "http",
+            _ => "https",
+        };
+        format!("{}://{}{}", scheme, self.bridge_ip(), path)
+    }
+
+    async fn send(&self, method: Method, path: &str, body: Option<&Value>) -> Result<Value, HueError> {
+        let mut request = HueRequest::new(method, self.url(path))
+            .header("hue-application-key", self.username.clone());
+        if let Some(body) = body {
+            request = request.body(body.clone());
+        }
+
+        Ok(config::send(&*self.transport, &self.config, request).await?.body)
+    }
+
+    // Opens a long lived response such as the event stream.
+    pub(crate) async fn stream(&self, path: &str) -> Result<ByteStream, HueError> {
+        let request = || HueRequest::new(Method::GET, self.url(path))
+            .header("hue-application-key", self.username.clone())
+            .header("accept", "text/event-stream");
+
+        match self.transport.stream(request()).await {
+            Err(err @ HueError::Unreachable { .. }) => {
+                match self.rediscover().await {
+                    Ok(true) => self.transport.stream(request()).await,
+                    _ => Err(err),
+                }
+            },
+            result => result,
+        }
+    }
+
+    pub async fn discover() -> Result::<String, HueError> {
+        let bridge = HueBridge::discover_bridges()
+            .await?
             .into_iter()
             .next() // Check first item exists and move to parsing it
             .ok_or(HueError::InvalidData { msg: "didn't find any bridges".into() })?;
 
-        let bridge = bridges["internalipaddress"].as_str()
-            .ok_or(HueError::InvalidData { msg: "couldn't parse bridge ip address".into() })?
-            .to_owned();
-        
-        Ok(bridge)
+        Ok(bridge.internal_ip_address)
+    }
+
+    pub async fn discover_bridges() -> Result::<Vec<DiscoveredBridge>, HueError> {
+        let config = ClientConfig::default();
+        HueBridge::discover_bridges_with(&*default_transport(&config), &config).await
+    }
+
+    #[tracing::instrument(name = "discover", skip_all)]
+    pub async fn discover_bridges_with(transport: &dyn HueTransport, config: &ClientConfig) -> Result::<Vec<DiscoveredBridge>, HueError> {
+        let request = HueRequest::new(Method::GET, "https://discovery.meethue.com/");
+        let response = config::send(transport, config, request)
+            .await?
+            .body; // Gives back a json array of every bridge on the network
+
+        let bridges: Vec<DiscoveredBridge> = serde_json::from_value(response)?;
+        tracing::debug!(found = bridges.len(), "discovery finished");
+        Ok(bridges)
+    }
+
+    pub async fn fetch_config(bridge_ip: &str) -> Result<BridgeConfig, HueError> {
+        let config = ClientConfig::default();
+        HueBridge::fetch_config_with(&*default_transport(&config), &config, bridge_ip).await
+    }
+
+    pub async fn fetch_config_with(transport: &dyn HueTransport, config: &ClientConfig, bridge_ip: &str) -> Result<BridgeConfig, HueError> {
+        let request = |scheme: &str| HueRequest::new(Method::GET, format!("{}://{}/api/0/config", scheme, bridge_ip));
+        let response = send_any_scheme(transport, config, request).await?.body;
+
+        Ok(serde_json::from_value(response)?)
     }
 
     pub async fn pair(bridge_ip: String) -> Result<HueBridge, HueError> {
-        let client = ClientBuilder::new()
-            .danger_accept_invalid_certs(true)
-            .build()?;
+        let config = ClientConfig::default();
+        HueBridge::pair_once(default_transport(&config), &config, bridge_ip, &Uuid::new_v4().to_string(), false).await
+    }
 
-        let req = format!("https://{}/api", bridge_ip);
+    pub(crate) async fn pair_once(transport: Arc<dyn HueTransport>, config: &ClientConfig, bridge_ip: String, device_type: &str, generate_client_key: bool) -> Result<HueBridge, HueError> {
+        let mut body = json!({ "devicetype": device_type });
+        if generate_client_key {
+            body["generateclientkey"] = json!(true);
+        }
 
-        let response = client.post(req)
-            .json(&json!({ "devicetype": Uuid::new_v4().to_string() }))
-            .send()
-            .await?
-            .json::<Value>()
-            .await?;
+        let request = |scheme: &str| HueRequest::new(Method::POST, format!("{}://{}/api", scheme, bridge_ip)).body(body.clone());
+        let response = send_any_scheme(&*transport, config, request).await?.body;
             
         let json_obj = serde_json::from_value::<Vec<Map<String, Value>>>(response)?
             .into_iter()
@@ -96,7 +362,18 @@ impl HueBridge {
                 .as_str()
                 .ok_or(HueError::InvalidData { msg: "Failed to parse username pair result.".to_string() })?.into();
 
-            Ok(HueBridge::new(bridge_ip, username))
+            let mut bridge = HueBridge::new(bridge_ip, username).with_transport(transport);
+            bridge.config = config.clone();
+            if let Some(client_key) = succes_obj.get("clientkey").and_then(|key| key.as_str()) {
+                bridge = bridge.with_client_key(client_key.into());
+            }
+
+            // Bridge id is only needed to remember the bridge, so pairing still succeeds without it.
+            if let Ok(config) = HueBridge::fetch_config_with(&*bridge.transport, config, &bridge.bridge_ip()).await {
+                bridge = bridge.with_bridge_id(config.bridge_id);
+            }
+
+            Ok(bridge)
         } else if json_obj["error"]["type"].as_i64().unwrap_or(0) == 101 {
             Err(HueError::LinkButtonNotPressed)
         } else {
@@ -105,14 +382,171 @@ impl HueBridge {
     }
 }
 
+// Used before the api version is known. First generation bridges refuse https connections, in
+// which case nothing was sent and the request is repeated over http.
+async fn send_any_scheme(transport: &dyn HueTransport, config: &ClientConfig, request: impl Fn(&str) -> HueRequest) -> Result<transport::HueResponse, HueError> {
+    match config::send(transport, config, request("https")).await {
+        Err(HueError::Unreachable { .. }) => config::send(transport, config, request("http")).await,
+        result => result,
+    }
+}
+
+// CLIP v2 responses carry failures in an "errors" array next to "data".
+pub(crate) fn check_errors(response: &Value) -> Result<(), HueError> {
+    let Some(errors) = response["errors"].as_array() else { return Ok(()); };
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    let msg = errors.iter()
+        .filter_map(|error| error["description"].as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    Err(HueError::Bridge { msg })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     #[allow(unused_imports)]
     use light::{ Color, Light };
+    use behavior::BehaviorTransaction;
+    use transport::MemoryTransport;
 
     static BRIDGE_IP: &str = "10.0.99.56";
-    static BRIDGE_KEY: &str = "9XkuVXXI4cxX9SpoCJosjbqvEUZncoX3TuvweAlS";
+
+    // Application keys grant full control of the bridge, so they are never committed.
+    fn bridge_key() -> String {
+        std::env::var("HUEY_BRIDGE_KEY").expect("HUEY_BRIDGE_KEY must be set for bridge tests")
+    }
+
+    #[test]
+    fn api_version_from_config() {
+        assert_eq!(ApiVersion::from_api_version("1.45.0"), ApiVersion::V1);
+        assert_eq!(ApiVersion::from_api_version("1.46.0"), ApiVersion::V2);
+        assert_eq!(ApiVersion::from_api_version("1.60.0"), ApiVersion::V2);
+        assert_eq!(ApiVersion::from_api_version("garbage"), ApiVersion::V1);
+    }
+
+    pub(crate) fn memory_bridge(transport: MemoryTransport) -> (HueBridge, Arc<MemoryTransport>) {
+        let transport = Arc::new(transport);
+        let bridge = HueBridge::new("10.0.0.2".into(), "test-key".into())
+            .with_api_version(ApiVersion::V2)
+            .with_transport(transport.clone());
+        (bridge, transport)
+    }
+
+    #[tokio::test]
+    async fn memory_list_lights() {
+        let lights_json = serde_json::from_str(include_str!("../example_json/lights.json")).unwrap();
+        let (bridge, transport) = memory_bridge(MemoryTransport::new()
+            .route(Method::GET, "/clip/v2/resource/light", lights_json));
+
+        let lights = Light::list_lights(&bridge).await.unwrap();
+        assert_eq!(lights[0].name, "Lamp");
+        assert_eq!(lights[0].id_v1.as_deref(), Some("/lights/5"));
+
+        let request = &transport.requests()[0];
+        assert_eq!(request.url, "https://10.0.0.2/clip/v2/resource/light");
+        assert!(request.headers.contains(&("hue-application-key".into(), "test-key".into())));
+    }
+
+    #[tokio::test]
+    async fn memory_toggle_light() {
+        let (bridge, transport) = memory_bridge(MemoryTransport::new()
+            .route(Method::PUT, "/clip/v2/resource/light/light-1", json!({ "errors": [], "data": [{ "rid": "light-1", "rtype": "light" }] })));
+
+        Light::toggle_power_id("light-1".into(), true).on(&bridge).await.unwrap();
+        assert_eq!(transport.requests()[0].body, Some(json!({ "on": { "on": true } })));
+    }
+
+    #[tokio::test]
+    async fn dry_run_withholds_changes() {
+        let lights_json = serde_json::from_str(include_str!("../example_json/lights.json")).unwrap();
+        let (bridge, transport) = memory_bridge(MemoryTransport::new()
+            .route(Method::GET, "/clip/v2/resource/light", lights_json));
+        let (dry_run, withheld) = bridge.dry_run();
+
+        let lamp = Light::list_lights(&dry_run).await.unwrap().remove(0);
+        lamp.toggle_power().on(&dry_run).await.unwrap();
+        let created = BehaviorTransaction::Create(json!({ "script_id": "script-1", "enabled": true })).on(&dry_run).await.unwrap();
+
+        assert!(!created.is_empty());
+        assert_eq!(transport.requests().len(), 1);
+        let withheld = withheld.requests();
+        assert_eq!(withheld.iter().map(|request| request.method.clone()).collect::<Vec<_>>(), vec![Method::PUT, Method::POST]);
+        assert_eq!(withheld[0].path(), format!("/clip/v2/resource/light/{}", lamp.id));
+
+        let options = pairing::PairOptions::new("huey", "test").transport(dry_run.transport().clone());
+        assert!(matches!(HueBridge::pair_with("10.0.0.2".into(), options, |_| {}).await, Err(HueError::Unsupported)));
+    }
+
+    #[tokio::test]
+    async fn rediscovers_moved_bridge() {
+        let transport = MemoryTransport::new()
+            .route(Method::GET, "https://discovery.meethue.com/", json!([{ "id": "ecb5fafffe000001", "internalipaddress": "10.0.0.9", "port": 443 }]))
+            .route(Method::GET, "https://10.0.0.9/clip/v2/resource/light", json!({ "errors": [], "data": [] }))
+            .with_fallback(|request| Err(HueError::Unreachable { msg: request.url.clone() }));
+        let (bridge, _) = memory_bridge(transport);
+        let bridge = bridge.with_bridge_id("ECB5FAFFFE000001".into());
+        let mut events = bridge.subscribe();
+
+        assert!(Light::list_lights(&bridge).await.unwrap().is_empty());
+        assert_eq!(bridge.bridge_ip(), "10.0.0.9");
+        assert_eq!(events.try_recv().unwrap(), BridgeEvent::IpChanged {
+            bridge_id: "ECB5FAFFFE000001".into(),
+            old_ip: "10.0.0.2".into(),
+            new_ip: "10.0.0.9".into(),
+        });
+    }
+
+    #[tokio::test]
+    async fn resends_only_retryable_timeouts() {
+        let transport = MemoryTransport::new()
+            .route(Method::GET, "https://discovery.meethue.com/", json!([{ "id": "ecb5fafffe000001", "internalipaddress": "10.0.0.9", "port": 443 }]))
+            .route(Method::GET, "https://10.0.0.9/clip/v2/resource/light", json!({ "errors": [], "data": [] }))
+            .with_fallback(|_| Err(HueError::Timeout));
+        let (bridge, transport) = memory_bridge(transport);
+        let bridge = bridge
+            .with_bridge_id("ECB5FAFFFE000001".into())
+            .with_config(ClientConfig::default().retry(config::RetryPolicy::none()));
+
+        let result = bridge.request(Method::POST, "/clip/v2/resource/behavior_instance", Some(&json!({}))).await;
+        assert!(matches!(result, Err(HueError::Timeout)));
+        assert_eq!(bridge.bridge_ip(), "10.0.0.9");
+        assert!(transport.requests().iter().all(|request| !request.url.starts_with("https://10.0.0.9/clip/v2/resource/behavior_instance")));
+
+        bridge.set_bridge_ip("10.0.0.2".into());
+        assert!(Light::list_lights(&bridge).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn detects_v1_bridge() {
+        let transport = Arc::new(MemoryTransport::new()
+            .route(Method::GET, "/api/0/config", json!({ "name": "Hue", "bridgeid": "ECB5FAFFFE000001", "apiversion": "1.16.0", "swversion": "01041302", "modelid": "BSB001" }))
+            .route(Method::GET, "/api/test-key/lights", json!({ "1": { "name": "Desk", "state": { "on": true, "bri": 254, "xy": [0.3, 0.3] } } })));
+        let bridge = HueBridge::new("10.0.0.2".into(), "test-key".into()).with_transport(transport.clone());
+
+        let lights = Light::list_lights(&bridge).await.unwrap();
+        assert_eq!(bridge.api_version().await.unwrap(), ApiVersion::V1);
+        assert_eq!(transport.requests()[1].url, "http://10.0.0.2/api/test-key/lights");
+        assert_eq!(lights[0].id, "/lights/1");
+        assert_eq!(lights[0].brightness, 100.0);
+        assert!(lights[0].capabilities.has_color() && !lights[0].capabilities.has_color_temperature());
+    }
+
+    #[tokio::test]
+    async fn pairs_over_http() {
+        let transport = Arc::new(MemoryTransport::new()
+            .route(Method::POST, "http://10.0.0.2/api", json!([{ "success": { "username": "new-key" } }]))
+            .route(Method::GET, "http://10.0.0.2/api/0/config", json!({ "name": "Hue", "bridgeid": "ECB5FAFFFE000001", "apiversion": "1.16.0", "swversion": "01041302", "modelid": "BSB001" }))
+            .with_fallback(|request| Err(HueError::Unreachable { msg: request.url.clone() })));
+
+        let bridge = HueBridge::pair_once(transport.clone(), &ClientConfig::default().retry(config::RetryPolicy::none()), "10.0.0.2".into(), "huey#test", false).await.unwrap();
+        assert_eq!(bridge.username, "new-key");
+        assert_eq!(bridge.bridge_id.as_deref(), Some("ECB5FAFFFE000001"));
+        assert_eq!(transport.requests().len(), 4);
+    }
 
     #[tokio::test]
     async fn discover_bridge() {
@@ -130,7 +564,7 @@ mod tests {
 
     #[tokio::test]
     async fn list_lights() {
-        let bridge = HueBridge::new(BRIDGE_IP.into(), BRIDGE_KEY.into());
+        let bridge = HueBridge::new(BRIDGE_IP.into(), bridge_key());
 
         let result = Light::list_lights(&bridge).await;
         assert_eq!(result.is_ok(), true);
@@ -138,7 +572,7 @@ mod tests {
 
     #[tokio::test]
     async fn toggle_light() {
-        let bridge = HueBridge::new(BRIDGE_IP.into(), BRIDGE_KEY.into());
+        let bridge = HueBridge::new(BRIDGE_IP.into(), bridge_key());
         let lights = Light::list_lights(&bridge).await.unwrap();
     
         for light in lights {
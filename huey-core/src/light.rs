@@ -1,8 +1,11 @@
 
 use serde::Serialize;
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
 
-use crate::{HueError, HueBridge};
+use crate::{ApiVersion, HueError, HueBridge, ResourceRef, v1};
+use crate::capabilities::Capabilities;
+use crate::powerup::PowerUp;
+use crate::resource::{self, Resource};
 
 #[derive(Debug, Clone, Copy, Serialize)]
 pub struct ColorXY { pub x: f64, pub y: f64, pub bri: f64 }
@@ -38,6 +41,10 @@ impl ColorRGB {
 }
 
 impl ColorXY {
+    // D65 white, reported for lights that have no color.
+    pub const WHITE_X: f64 = 0.3127;
+    pub const WHITE_Y: f64 = 0.329;
+
     pub fn new(rgb: &[f64; 3]) -> Self {
         ColorRGB { r: rgb[0].into(), g: rgb[1].into(), b: rgb[2].into() }.as_xy()
     }
@@ -70,54 +77,26 @@ impl ColorXY {
 pub struct Light {
     #[allow(unused_assignments)]
     pub id: String,
+    // Legacy v1 path such as "/lights/5", on v1 bridges this is also the id.
+    pub id_v1: Option<String>,
     pub name: String,
     pub is_on: bool,
     pub brightness: f64,
     pub min_brightness: f64,
     pub color: ColorXY,
+    pub capabilities: Capabilities,
+    // Fields not covered above, such as the owner device, color gamut or effects.
+    pub extras: Map<String, Value>,
 }
 
 #[allow(dead_code)]
 impl Light {
-    pub async fn list_lights(bridge: HueBridge) -> Result<Vec<Light>, HueError> {
-        let req = format!("https://{}{}", bridge.bridge_ip, "/clip/v2/resource/light");
-
-        let client = bridge.build_client()?;
-        let json_response = client.get(req)
-            .send()
-            .await?
-            .json::<Value>()
-            .await?;
-
-        let lights = serde_json::from_value::<serde_json::Map<String, Value>>(json_response)?["data"]
-            .as_array()
-            .ok_or(HueError::InvalidData { msg: "couldn't parse list_lights data json object".into() })?
-            .into_iter()
-            .filter_map(|in_value| {
-                if let Value::Object(x) = in_value {
-                    let is_on = x["on"]["on"].as_bool()?;
-                    let brightness = x["dimming"]["brightness"].as_f64()?;
-                    let color_x = x["color"]["xy"]["x"].as_f64()?;
-                    let color_y = x["color"]["xy"]["y"].as_f64()?;
-                    Some(Light {
-                        id: x["id"].as_str()?.into(), 
-                        name: x["metadata"]["name"].as_str()?.into(),
-                        is_on,
-                        brightness,
-                        min_brightness: x["dimming"]["min_dim_level"].as_f64()?,
-                        color: ColorXY {
-                            x: color_x,
-                            y: color_y,
-                            bri: brightness
-                        },
-                    })
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-        Ok(lights)
+    pub async fn list_lights(bridge: &HueBridge) -> Result<Vec<Light>, HueError> {
+        if bridge.api_version().await? == ApiVersion::V1 {
+            return v1::list_lights(bridge).await;
+        }
+
+        resource::list_as::<Light>(bridge).await
     }
 
     pub fn toggle_power(&self) -> LightTransaction {
@@ -131,6 +110,33 @@ impl Light {
         }
     }
 
+    // E.g. "sultan_bulb" or "floor_shade", Hue apps pick the icon from it.
+    pub fn archetype(&self) -> Option<&str> {
+        self.extras.get("metadata")?["archetype"].as_str()
+    }
+
+    pub fn powerup(&self) -> Option<PowerUp> {
+        PowerUp::from_json(self.extras.get("powerup")?)
+    }
+
+    pub fn rename(&self, name: impl Into<String>) -> LightTransaction {
+        Light::rename_id(self.id.clone(), name)
+    }
+
+    pub fn rename_id(light_id: String, name: impl Into<String>) -> LightTransaction {
+        LightTransaction { light_id, body: json!({ "metadata": { "name": name.into() } }) }
+    }
+
+    // Not available on v1 bridges.
+    pub fn set_archetype_id(light_id: String, archetype: impl Into<String>) -> LightTransaction {
+        LightTransaction { light_id, body: json!({ "metadata": { "archetype": archetype.into() } }) }
+    }
+
+    // Not available on v1 bridges.
+    pub fn set_powerup_id(light_id: String, powerup: &PowerUp) -> LightTransaction {
+        LightTransaction { light_id, body: json!({ "powerup": powerup.to_json() }) }
+    }
+
     pub fn change_color(&self, color: Option<Color>, brightness: Option<f64>) -> LightTransaction {
         Light::change_color_id(self.id.clone(), color, brightness, Some(self.min_brightness))
     }
@@ -162,6 +168,57 @@ impl Light {
     }
 }
 
+impl Resource for Light {
+    const RTYPE: &'static str = "light";
+
+    // Lights without dimming or color report full brightness and D65 white, check `capabilities`
+    // before showing either.
+    fn from_json(value: &Value) -> Option<Light> {
+        let Value::Object(x) = value else { return None; };
+
+        let is_on = x["on"]["on"].as_bool()?;
+        let brightness = x["dimming"]["brightness"].as_f64().unwrap_or(100.0);
+        let color_x = x["color"]["xy"]["x"].as_f64().unwrap_or(ColorXY::WHITE_X);
+        let color_y = x["color"]["xy"]["y"].as_f64().unwrap_or(ColorXY::WHITE_Y);
+        let mut light = Light {
+            id: x["id"].as_str()?.into(),
+            id_v1: x.get("id_v1").and_then(|id_v1| id_v1.as_str()).map(|id_v1| id_v1.into()),
+            name: x["metadata"]["name"].as_str()?.into(),
+            is_on,
+            brightness,
+            min_brightness: x["dimming"]["min_dim_level"].as_f64().unwrap_or(0.0),
+            color: ColorXY {
+                x: color_x,
+                y: color_y,
+                bri: brightness
+            },
+            capabilities: Capabilities::from_json(value),
+            extras: Map::new(),
+        };
+        light.extras = resource::unknown_fields(value, &light.to_json());
+        Some(light)
+    }
+
+    fn to_json(&self) -> Value {
+        let mut known = json!({
+            "id": self.id,
+            "type": Light::RTYPE,
+            "metadata": { "name": self.name },
+            "on": { "on": self.is_on },
+        });
+        if self.capabilities.is_dimmable() {
+            known["dimming"] = json!({ "brightness": self.brightness, "min_dim_level": self.min_brightness });
+        }
+        if self.capabilities.has_color() {
+            known["color"] = json!({ "xy": { "x": self.color.x, "y": self.color.y } });
+        }
+        if let Some(id_v1) = &self.id_v1 {
+            known["id_v1"] = json!(id_v1);
+        }
+        resource::with_extras(known, &self.extras)
+    }
+}
+
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct LightTransaction {
@@ -171,20 +228,78 @@ pub struct LightTransaction {
 
 #[allow(dead_code)]
 impl LightTransaction {
+    pub(crate) fn new(light_id: String, body: Value) -> LightTransaction {
+        LightTransaction { light_id, body }
+    }
+
+    pub fn light_id(&self) -> &str {
+        &self.light_id
+    }
+
+    pub(crate) fn body(&self) -> &Value {
+        &self.body
+    }
+
     pub async fn on(&self, bridge: &HueBridge) -> Result<(), HueError> {
-        let mut req = format!("https://{}", bridge.bridge_ip);
-        req.push_str("/clip/v2/resource/light/");
-        req.push_str(&self.light_id);
-
-        let client = bridge.build_client()?;
-        client
-            .put(req)
-            .json(&self.body)
-            .send()
-            .await?
-            .json::<Value>()
-            .await?;
+        if bridge.api_version().await? == ApiVersion::V1 {
+            return v1::put_light(bridge, &self.light_id, &self.body).await;
+        }
 
+        ResourceRef::new(self.light_id.clone(), Light::RTYPE).put(bridge, &self.body).await?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // lights.json with fields a newer firmware might add at every level.
+    fn lights_with_new_fields() -> Vec<Value> {
+        let mut lights = serde_json::from_str::<Value>(include_str!("../example_json/lights.json")).unwrap();
+        let mut data = lights["data"].take().as_array().unwrap().clone();
+        for light in data.iter_mut() {
+            light["future_feature"] = json!({ "enabled": true });
+            light["dimming"]["curve"] = json!("logarithmic");
+            light["metadata"]["function"] = json!("decorative");
+            light["color"]["xy"]["z"] = json!(0.1);
+        }
+        data
+    }
+
+    #[test]
+    fn parses_lights_with_new_fields() {
+        let data = lights_with_new_fields();
+        let lights = data.iter().filter_map(Light::from_json).collect::<Vec<_>>();
+        assert_eq!(lights.len(), data.len());
+
+        let lamp = &lights[0];
+        assert_eq!(lamp.name, "Lamp");
+        assert_eq!(lamp.extras["future_feature"], json!({ "enabled": true }));
+        assert_eq!(lamp.extras["dimming"], json!({ "curve": "logarithmic" }));
+        assert!(!lamp.extras.contains_key("id"));
+    }
+
+    #[test]
+    fn edits_metadata() {
+        let lamp = Light::from_json(&lights_with_new_fields()[0]).unwrap();
+        assert_eq!(lamp.archetype(), Some("sultan_bulb"));
+        assert!(lamp.powerup().is_some());
+        assert_eq!(lamp.rename("Desk lamp").body, json!({ "metadata": { "name": "Desk lamp" } }));
+    }
+
+    #[test]
+    fn round_trips_unknown_fields() {
+        let original = &lights_with_new_fields()[0];
+        let mut lamp = Light::from_json(original).unwrap();
+        lamp.name = "Desk lamp".into();
+
+        let written = lamp.to_json();
+        assert_eq!(written["metadata"]["name"], "Desk lamp");
+        assert_eq!(written["metadata"]["archetype"], original["metadata"]["archetype"]);
+        assert_eq!(written["metadata"]["function"], "decorative");
+        assert_eq!(written["dimming"]["curve"], "logarithmic");
+        assert_eq!(written["color"]["xy"]["z"], 0.1);
+        assert_eq!(written["color"]["gamut"], original["color"]["gamut"]);
+        assert_eq!(written["owner"], original["owner"]);
+    }
+}
@@ -0,0 +1,150 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::{HueError, HueBridge};
+use crate::config::ClientConfig;
+use crate::transport::{default_transport, HueTransport};
+
+// The bridge rejects a devicetype longer than 40 characters ("<app>#<device>").
+const MAX_APP_NAME_LEN: usize = 20;
+const MAX_DEVICE_NAME_LEN: usize = 19;
+
+#[derive(Debug, Clone)]
+pub struct PairOptions {
+    pub app_name: String,
+    pub device_name: String,
+    pub timeout: Duration,
+    pub poll_interval: Duration,
+    pub generate_client_key: bool,
+    pub transport: Option<Arc<dyn HueTransport>>,
+    // Timeouts and retries for pairing and the paired bridge, the default config when `None`.
+    pub config: Option<ClientConfig>,
+}
+
+impl PairOptions {
+    pub fn new(app_name: impl Into<String>, device_name: impl Into<String>) -> Self {
+        Self {
+            app_name: app_name.into(),
+            device_name: device_name.into(),
+            timeout: Duration::from_secs(30),
+            poll_interval: Duration::from_secs(1),
+            generate_client_key: true,
+            transport: None,
+            config: None,
+        }
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn generate_client_key(mut self, generate_client_key: bool) -> Self {
+        self.generate_client_key = generate_client_key;
+        self
+    }
+
+    pub fn transport(mut self, transport: Arc<dyn HueTransport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    pub fn config(mut self, config: ClientConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    pub fn device_type(&self) -> Result<String, HueError> {
+        if self.app_name.is_empty() || self.app_name.chars().count() > MAX_APP_NAME_LEN {
+            return Err(HueError::InvalidData { msg: format!("app name must be 1-{} characters", MAX_APP_NAME_LEN) });
+        }
+        if self.device_name.chars().count() > MAX_DEVICE_NAME_LEN {
+            return Err(HueError::InvalidData { msg: format!("device name must be at most {} characters", MAX_DEVICE_NAME_LEN) });
+        }
+        Ok(format!("{}#{}", self.app_name, self.device_name))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairProgress {
+    // The bridge answered but the link button hasn't been pressed yet.
+    WaitingForLinkButton { attempt: u32, remaining: Duration },
+    Paired,
+}
+
+impl HueBridge {
+    // Polls the bridge until its link button is pressed or `options.timeout` passes.
+    #[tracing::instrument(name = "pair", skip_all, fields(bridge_ip = %bridge_ip, app = %options.app_name, device = %options.device_name))]
+    pub async fn pair_with(
+        bridge_ip: String,
+        options: PairOptions,
+        mut on_progress: impl FnMut(PairProgress),
+    ) -> Result<HueBridge, HueError> {
+        let device_type = options.device_type()?;
+        let config = options.config.clone().unwrap_or_default();
+        let transport = options.transport.clone().unwrap_or_else(|| default_transport(&config));
+        let started = Instant::now();
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+            match HueBridge::pair_once(transport.clone(), &config, bridge_ip.clone(), &device_type, options.generate_client_key).await {
+                Err(HueError::LinkButtonNotPressed) => {
+                    let remaining = options.timeout.saturating_sub(started.elapsed());
+                    if remaining.is_zero() {
+                        tracing::warn!(attempt, "link button wasn't pressed before the timeout");
+                        return Err(HueError::PairTimeout);
+                    }
+
+                    tracing::debug!(attempt, ?remaining, "waiting for link button");
+                    on_progress(PairProgress::WaitingForLinkButton { attempt, remaining });
+                    tokio::time::sleep(options.poll_interval.min(remaining)).await;
+                },
+                Ok(bridge) => {
+                    tracing::info!(attempt, bridge_id = bridge.bridge_id.as_deref(), "paired");
+                    on_progress(PairProgress::Paired);
+                    return Ok(bridge);
+                },
+                Err(err) => {
+                    tracing::warn!(attempt, error = %err, "pairing failed");
+                    return Err(err);
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::transport::MemoryTransport;
+
+    #[test]
+    fn device_type_limits() {
+        assert_eq!(PairOptions::new("huey", "desktop").device_type().unwrap(), "huey#desktop");
+        assert!(PairOptions::new("", "desktop").device_type().is_err());
+        assert!(PairOptions::new("a".repeat(21), "desktop").device_type().is_err());
+        assert!(PairOptions::new("huey", "d".repeat(20)).device_type().is_err());
+    }
+
+    #[tokio::test]
+    async fn pairs_with_config() {
+        let transport = MemoryTransport::new()
+            .route(Method::POST, "/api", json!([{ "success": { "username": "new-key" } }]))
+            .route(Method::GET, "/api/0/config", json!({ "name": "Hue", "bridgeid": "ECB5FAFFFE000001", "apiversion": "1.60.0", "swversion": "1960135080", "modelid": "BSB002" }));
+        let config = ClientConfig::default().request_timeout(Duration::from_secs(2));
+        let options = PairOptions::new("huey", "desktop").transport(Arc::new(transport)).config(config.clone());
+
+        let bridge = HueBridge::pair_with("10.0.0.2".into(), options, |_| {}).await.unwrap();
+        assert_eq!(bridge.config(), &config);
+        assert_eq!(bridge.bridge_id.as_deref(), Some("ECB5FAFFFE000001"));
+    }
+}
@@ -0,0 +1,142 @@
+// What a light does when power returns, e.g. after a power cut or when switched on at the wall.
+
+use serde_json::{json, Map, Value};
+
+open_enum! {
+    pub enum PowerUpPreset {
+        // Full brightness warm white, the factory default.
+        Safety => "safety",
+        // Back to the state before the power cut, off lights stay off.
+        PowerFail => "powerfail",
+        // Back to the last state the light was on with, even if it was off.
+        LastOnState => "last_on_state",
+        Custom => "custom",
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowerUpOn {
+    On,
+    Off,
+    // Off if it was on and the other way around.
+    Toggle,
+    Previous,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowerUpDimming {
+    Brightness(f64),
+    Previous,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowerUpColor {
+    // In mirek.
+    Temperature(u32),
+    XY { x: f64, y: f64 },
+    Previous,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PowerUp {
+    pub preset: PowerUpPreset,
+    // Whether the light has applied the preset yet, read only.
+    pub configured: bool,
+    pub on: Option<PowerUpOn>,
+    pub dimming: Option<PowerUpDimming>,
+    pub color: Option<PowerUpColor>,
+}
+
+impl PowerUp {
+    pub fn preset(preset: PowerUpPreset) -> PowerUp {
+        PowerUp { preset, configured: false, on: None, dimming: None, color: None }
+    }
+
+    // Fields left as `None` are not part of the custom behavior.
+    pub fn custom(on: PowerUpOn, dimming: Option<PowerUpDimming>, color: Option<PowerUpColor>) -> PowerUp {
+        PowerUp { preset: PowerUpPreset::Custom, configured: false, on: Some(on), dimming, color }
+    }
+
+    pub fn from_json(value: &Value) -> Option<PowerUp> {
+        let preset = PowerUpPreset::from(value["preset"].as_str()?);
+
+        let on = match value["on"]["mode"].as_str() {
+            Some("on") if value["on"]["on"]["on"].as_bool() == Some(false) => Some(PowerUpOn::Off),
+            Some("on") => Some(PowerUpOn::On),
+            Some("toggle") => Some(PowerUpOn::Toggle),
+            Some("previous") => Some(PowerUpOn::Previous),
+            _ => None,
+        };
+        let dimming = match value["dimming"]["mode"].as_str() {
+            Some("dimming") => value["dimming"]["dimming"]["brightness"].as_f64().map(PowerUpDimming::Brightness),
+            Some("previous") => Some(PowerUpDimming::Previous),
+            _ => None,
+        };
+        let color = match value["color"]["mode"].as_str() {
+            Some("color_temperature") => value["color"]["color_temperature"]["mirek"].as_u64().map(|mirek| PowerUpColor::Temperature(mirek as u32)),
+            Some("color") => {
+                let xy = &value["color"]["color"]["xy"];
+                Some(PowerUpColor::XY { x: xy["x"].as_f64()?, y: xy["y"].as_f64()? })
+            },
+            Some("previous") => Some(PowerUpColor::Previous),
+            _ => None,
+        };
+
+        Some(PowerUp { preset, configured: value["configured"].as_bool().unwrap_or(false), on, dimming, color })
+    }
+
+    // The body for a light's `powerup` field. Presets other than custom only need their name.
+    pub fn to_json(&self) -> Value {
+        let mut powerup = Map::new();
+        powerup.insert("preset".into(), json!(self.preset));
+        if self.preset != PowerUpPreset::Custom {
+            return powerup.into();
+        }
+
+        if let Some(on) = self.on {
+            powerup.insert("on".into(), match on {
+                PowerUpOn::On => json!({ "mode": "on", "on": { "on": true } }),
+                PowerUpOn::Off => json!({ "mode": "on", "on": { "on": false } }),
+                PowerUpOn::Toggle => json!({ "mode": "toggle" }),
+                PowerUpOn::Previous => json!({ "mode": "previous" }),
+            });
+        }
+        if let Some(dimming) = self.dimming {
+            powerup.insert("dimming".into(), match dimming {
+                PowerUpDimming::Brightness(brightness) => json!({ "mode": "dimming", "dimming": { "brightness": brightness } }),
+                PowerUpDimming::Previous => json!({ "mode": "previous" }),
+            });
+        }
+        if let Some(color) = self.color {
+            powerup.insert("color".into(), match color {
+                PowerUpColor::Temperature(mirek) => json!({ "mode": "color_temperature", "color_temperature": { "mirek": mirek } }),
+                PowerUpColor::XY { x, y } => json!({ "mode": "color", "color": { "xy": { "x": x, "y": y } } }),
+                PowerUpColor::Previous => json!({ "mode": "previous" }),
+            });
+        }
+        powerup.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_and_writes_powerup() {
+        let lights = serde_json::from_str::<Value>(include_str!("../example_json/lights.json")).unwrap();
+        let powerup = PowerUp::from_json(&lights["data"][0]["powerup"]).unwrap();
+        assert_eq!(powerup.preset, PowerUpPreset::Safety);
+        assert_eq!(powerup.on, Some(PowerUpOn::On));
+        assert_eq!(powerup.color, Some(PowerUpColor::Temperature(366)));
+        assert_eq!(powerup.to_json(), json!({ "preset": "safety" }));
+
+        let custom = PowerUp::custom(PowerUpOn::Previous, Some(PowerUpDimming::Brightness(40.0)), None);
+        assert_eq!(custom.to_json(), json!({
+            "preset": "custom",
+            "on": { "mode": "previous" },
+            "dimming": { "mode": "dimming", "dimming": { "brightness": 40.0 } }
+        }));
+        assert_eq!(PowerUp::from_json(&custom.to_json()).unwrap().dimming, custom.dimming);
+    }
+}
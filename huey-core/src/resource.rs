@@ -0,0 +1,151 @@
+// Generic access to any CLIP v2 resource type. Typed models implement `Resource`, anything
+// else can still be read and changed as plain json through a `ResourceRef`.
+
+use reqwest::Method;
+use serde_json::{Map, Value};
+
+use crate::{HueError, HueBridge, ResourceRef, check_errors};
+
+const RESOURCE_PATH: &str = "/clip/v2/resource";
+
+// A CLIP v2 resource with a typed model. Fields the model doesn't know about are kept,
+// so `to_json` gives back everything that was parsed.
+pub trait Resource: Sized {
+    const RTYPE: &'static str;
+
+    // Resources that don't fit the model are skipped when listing.
+    fn from_json(value: &Value) -> Option<Self>;
+
+    fn to_json(&self) -> Value;
+}
+
+impl ResourceRef {
+    pub fn new(rid: impl Into<String>, rtype: impl Into<String>) -> ResourceRef {
+        ResourceRef { rid: rid.into(), rtype: rtype.into() }
+    }
+
+    fn path(&self) -> String {
+        format!("{}/{}/{}", RESOURCE_PATH, self.rtype, self.rid)
+    }
+
+    pub async fn get(&self, bridge: &HueBridge) -> Result<Value, HueError> {
+        request_data(bridge, Method::GET, &self.path(), None).await?
+            .into_iter()
+            .next()
+            .ok_or(HueError::InvalidData { msg: format!("couldn't find {} {}", self.rtype, self.rid) })
+    }
+
+    pub async fn get_as<T: Resource>(&self, bridge: &HueBridge) -> Result<T, HueError> {
+        T::from_json(&self.get(bridge).await?)
+            .ok_or(HueError::InvalidData { msg: format!("couldn't parse {} {}", self.rtype, self.rid) })
+    }
+
+    // Returns the resources the bridge reports as changed.
+    pub async fn put(&self, bridge: &HueBridge, body: &Value) -> Result<Vec<ResourceRef>, HueError> {
+        let data = request_data(bridge, Method::PUT, &self.path(), Some(body)).await?;
+        Ok(serde_json::from_value(Value::Array(data))?)
+    }
+
+    pub async fn delete(&self, bridge: &HueBridge) -> Result<Vec<ResourceRef>, HueError> {
+        let data = request_data(bridge, Method::DELETE, &self.path(), None).await?;
+        Ok(serde_json::from_value(Value::Array(data))?)
+    }
+}
+
+// Every resource of `rtype`, or every resource on the bridge when `rtype` is empty.
+pub async fn list(bridge: &HueBridge, rtype: &str) -> Result<Vec<Value>, HueError> {
+    let path = if rtype.is_empty() { RESOURCE_PATH.to_string() } else { format!("{}/{}", RESOURCE_PATH, rtype) };
+    request_data(bridge, Method::GET, &path, None).await
+}
+
+pub async fn list_as<T: Resource>(bridge: &HueBridge) -> Result<Vec<T>, HueError> {
+    let resources = list(bridge, T::RTYPE).await?;
+    Ok(resources.iter().filter_map(T::from_json).collect())
+}
+
+// Creates a resource and returns a reference to it.
+pub async fn post(bridge: &HueBridge, rtype: &str, body: &Value) -> Result<ResourceRef, HueError> {
+    let path = format!("{}/{}", RESOURCE_PATH, rtype);
+    request_data(bridge, Method::POST, &path, Some(body)).await?
+        .into_iter()
+        .next()
+        .map(serde_json::from_value)
+        .transpose()?
+        .ok_or(HueError::InvalidData { msg: format!("couldn't parse created {}", rtype) })
+}
+
+// Fields of `value` missing from `known`, the json of a model without extras.
+pub(crate) fn unknown_fields(value: &Value, known: &Value) -> Map<String, Value> {
+    let Value::Object(value) = value else { return Map::new(); };
+
+    value.iter()
+        .filter_map(|(key, field)| match known.get(key) {
+            None => Some((key.clone(), field.clone())),
+            Some(known @ Value::Object(_)) => {
+                let unknown = unknown_fields(field, known);
+                (!unknown.is_empty()).then(|| (key.clone(), Value::Object(unknown)))
+            },
+            Some(_) => None,
+        })
+        .collect()
+}
+
+// Deep merges `update` into `target`, objects are merged key by key and anything else replaced.
+pub(crate) fn merge(target: &mut Value, update: &Value) {
+    match (target, update) {
+        (Value::Object(target), Value::Object(update)) => {
+            for (key, value) in update {
+                match target.get_mut(key) {
+                    Some(existing) => merge(existing, value),
+                    None => { target.insert(key.clone(), value.clone()); },
+                }
+            }
+        },
+        (target, update) => *target = update.clone(),
+    }
+}
+
+// A model's json with its extras merged back in.
+pub(crate) fn with_extras(known: Value, extras: &Map<String, Value>) -> Value {
+    let mut value = Value::Object(extras.clone());
+    merge(&mut value, &known);
+    value
+}
+
+async fn request_data(bridge: &HueBridge, method: Method, path: &str, body: Option<&Value>) -> Result<Vec<Value>, HueError> {
+    let mut response = bridge.request(method, path, body).await?;
+    check_errors(&response)?;
+
+    match response["data"].take() {
+        Value::Array(data) => Ok(data),
+        _ => Err(HueError::InvalidData { msg: format!("couldn't parse data of {}", path) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::tests::memory_bridge;
+    use crate::transport::MemoryTransport;
+
+    #[tokio::test]
+    async fn untyped_resources() {
+        let contact = json!({ "id": "contact-1", "type": "contact", "contact_report": { "state": "no_contact" } });
+        let (bridge, transport) = memory_bridge(MemoryTransport::new()
+            .route(Method::GET, "/clip/v2/resource/contact", json!({ "errors": [], "data": [contact] }))
+            .route(Method::PUT, "/clip/v2/resource/contact/contact-1", json!({ "errors": [], "data": [{ "rid": "contact-1", "rtype": "contact" }] }))
+            .route(Method::POST, "/clip/v2/resource/contact", json!({ "errors": [{ "description": "method, POST, not available for resource, /contact" }], "data": [] })));
+
+        let contacts = list(&bridge, "contact").await.unwrap();
+        assert_eq!(contacts[0]["contact_report"]["state"], "no_contact");
+
+        let reference = ResourceRef::new("contact-1", "contact");
+        let changed = reference.put(&bridge, &json!({ "enabled": false })).await.unwrap();
+        assert_eq!(changed, vec![reference]);
+        assert_eq!(transport.requests()[1].body, Some(json!({ "enabled": false })));
+
+        assert!(matches!(post(&bridge, "contact", &json!({})).await, Err(HueError::Bridge { .. })));
+    }
+}
@@ -0,0 +1,267 @@
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::{ApiVersion, HueError, HueBridge, ResourceRef, v1};
+use crate::resource;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoomKind {
+    // Rooms group devices, a device can only be in one room.
+    Room,
+    // Zones group light services and may overlap with rooms and other zones.
+    Zone,
+}
+
+impl RoomKind {
+    pub fn rtype(&self) -> &'static str {
+        match self {
+            RoomKind::Room => "room",
+            RoomKind::Zone => "zone",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RoomMetadata {
+    pub name: String,
+    pub archetype: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RoomData {
+    id: String,
+    metadata: RoomMetadata,
+    #[serde(default)]
+    children: Vec<ResourceRef>,
+    #[serde(default)]
+    services: Vec<ResourceRef>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Room {
+    pub id: String,
+    pub kind: RoomKind,
+    pub name: String,
+    pub archetype: String,
+    pub children: Vec<ResourceRef>,
+    pub services: Vec<ResourceRef>,
+    // Fields not covered above, kept so `to_json` doesn't drop them.
+    pub extras: Map<String, Value>,
+}
+
+impl Room {
+    pub async fn list_rooms(bridge: &HueBridge) -> Result<Vec<Room>, HueError> {
+        Room::list(bridge, RoomKind::Room).await
+    }
+
+    pub async fn list_zones(bridge: &HueBridge) -> Result<Vec<Room>, HueError> {
+        Room::list(bridge, RoomKind::Zone).await
+    }
+
+    async fn list(bridge: &HueBridge, kind: RoomKind) -> Result<Vec<Room>, HueError> {
+        if bridge.api_version().await? == ApiVersion::V1 {
+            return v1::list_groups(bridge, kind).await;
+        }
+
+        let rooms = resource::list(bridge, kind.rtype()).await?
+            .iter()
+            .filter_map(|room| Room::from_json(kind, room))
+            .collect();
+
+        Ok(rooms)
+    }
+
+    // Rooms and zones share a model, so they can't implement `Resource` with a single rtype.
+    pub(crate) fn from_json(kind: RoomKind, value: &Value) -> Option<Room> {
+        let room = RoomData::deserialize(value).ok()?;
+        let mut room = Room {
+            id: room.id,
+            kind,
+            name: room.metadata.name,
+            archetype: room.metadata.archetype,
+            children: room.children,
+            services: room.services,
+            extras: Map::new(),
+        };
+        room.extras = resource::unknown_fields(value, &room.to_json());
+        Some(room)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let known = json!({
+            "id": self.id,
+            "type": self.kind.rtype(),
+            "metadata": { "name": self.name, "archetype": self.archetype },
+            "children": self.children,
+            "services": self.services
+        });
+        resource::with_extras(known, &self.extras)
+    }
+
+    // Rooms and zones can only be edited on CLIP v2 bridges.
+    pub fn create(kind: RoomKind, name: impl Into<String>, archetype: impl Into<String>) -> RoomTransaction {
+        let body = json!({ "metadata": { "name": name.into(), "archetype": archetype.into() }, "children": [] });
+        RoomTransaction { kind, room_id: None, edit: RoomEdit::Create(body) }
+    }
+
+    pub fn rename(&self, name: impl Into<String>) -> RoomTransaction {
+        self.edit(RoomEdit::Update(json!({ "metadata": { "name": name.into() } })))
+    }
+
+    // E.g. "living_room" or "bedroom", the Hue apps pick the icon from it.
+    pub fn set_archetype(&self, archetype: impl Into<String>) -> RoomTransaction {
+        self.edit(RoomEdit::Update(json!({ "metadata": { "archetype": archetype.into() } })))
+    }
+
+    // Devices for rooms, light services for zones.
+    pub fn add_children(&self, children: Vec<ResourceRef>) -> RoomTransaction {
+        self.edit(RoomEdit::AddChildren(children))
+    }
+
+    pub fn remove_children(&self, children: Vec<ResourceRef>) -> RoomTransaction {
+        self.edit(RoomEdit::RemoveChildren(children))
+    }
+
+    // Devices in a deleted room are left without a room, their lights keep working.
+    pub fn delete(&self) -> RoomTransaction {
+        self.edit(RoomEdit::Delete)
+    }
+
+    fn edit(&self, edit: RoomEdit) -> RoomTransaction {
+        RoomTransaction { kind: self.kind, room_id: Some(self.id.clone()), edit }
+    }
+
+    // The grouped_light service that controls every light in the room at once.
+    // On v1 bridges this is the group itself.
+    pub fn grouped_light_id(&self) -> Option<&str> {
+        self.services.iter()
+            .find(|service| service.rtype == "grouped_light")
+            .map(|service| service.rid.as_str())
+    }
+}
+
+#[derive(Debug)]
+enum RoomEdit {
+    Create(Value),
+    Update(Value),
+    AddChildren(Vec<ResourceRef>),
+    RemoveChildren(Vec<ResourceRef>),
+    Delete,
+}
+
+#[derive(Debug)]
+pub struct RoomTransaction {
+    kind: RoomKind,
+    room_id: Option<String>,
+    edit: RoomEdit,
+}
+
+impl RoomTransaction {
+    // Returns the room or zone that was created, changed or deleted. Membership changes read the
+    // current children first, so they don't undo changes made elsewhere since the room was listed.
+    pub async fn on(&self, bridge: &HueBridge) -> Result<ResourceRef, HueError> {
+        if bridge.api_version().await? == ApiVersion::V1 {
+            return Err(HueError::Unsupported);
+        }
+
+        let room = ResourceRef::new(self.room_id.clone().unwrap_or_default(), self.kind.rtype());
+        match &self.edit {
+            RoomEdit::Create(body) => return resource::post(bridge, self.kind.rtype(), body).await,
+            RoomEdit::Update(body) => { room.put(bridge, body).await?; },
+            RoomEdit::Delete => { room.delete(bridge).await?; },
+            RoomEdit::AddChildren(added) => {
+                check_children(bridge, self.kind, &room.rid, added).await?;
+                let mut children = current_children(bridge, self.kind, &room).await?;
+                for child in added {
+                    if !children.contains(child) {
+                        children.push(child.clone());
+                    }
+                }
+                room.put(bridge, &json!({ "children": children })).await?;
+            },
+            RoomEdit::RemoveChildren(removed) => {
+                let mut children = current_children(bridge, self.kind, &room).await?;
+                if let Some(missing) = removed.iter().find(|child| !children.contains(child)) {
+                    return Err(HueError::InvalidMembership { msg: format!("{} {} is not in {} {}", missing.rtype, missing.rid, self.kind.rtype(), room.rid) });
+                }
+                children.retain(|child| !removed.contains(child));
+                room.put(bridge, &json!({ "children": children })).await?;
+            },
+        }
+        Ok(room)
+    }
+}
+
+async fn current_children(bridge: &HueBridge, kind: RoomKind, room: &ResourceRef) -> Result<Vec<ResourceRef>, HueError> {
+    let current = Room::from_json(kind, &room.get(bridge).await?)
+        .ok_or(HueError::InvalidData { msg: format!("couldn't parse {} {}", room.rtype, room.rid) })?;
+    Ok(current.children)
+}
+
+// Rooms hold devices and a device can only be in one room, zones hold light services and may overlap.
+async fn check_children(bridge: &HueBridge, kind: RoomKind, room_id: &str, children: &[ResourceRef]) -> Result<(), HueError> {
+    let allowed = match kind {
+        RoomKind::Room => "device",
+        RoomKind::Zone => "light",
+    };
+    if let Some(child) = children.iter().find(|child| child.rtype != allowed) {
+        return Err(HueError::InvalidMembership { msg: format!("a {} can't hold a {}, only a {}", kind.rtype(), child.rtype, allowed) });
+    }
+    if kind == RoomKind::Zone {
+        return Ok(());
+    }
+
+    for other in Room::list_rooms(bridge).await?.iter().filter(|other| other.id != room_id) {
+        if let Some(child) = children.iter().find(|child| other.children.contains(child)) {
+            return Err(HueError::InvalidMembership { msg: format!("device {} is already in room {:?}, remove it there first", child.rid, other.name) });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Method;
+
+    use super::*;
+    use crate::tests::memory_bridge;
+    use crate::transport::MemoryTransport;
+
+    fn rooms() -> Value {
+        json!({ "errors": [], "data": [
+            { "id": "room-1", "type": "room", "metadata": { "name": "Living room", "archetype": "living_room" }, "children": [{ "rid": "device-1", "rtype": "device" }], "services": [] },
+            { "id": "room-2", "type": "room", "metadata": { "name": "Bedroom", "archetype": "bedroom" }, "children": [{ "rid": "device-2", "rtype": "device" }], "services": [] },
+        ]})
+    }
+
+    #[tokio::test]
+    async fn edits_room_membership() {
+        let room = json!({ "errors": [], "data": [rooms()["data"][0].clone()] });
+        let (bridge, transport) = memory_bridge(MemoryTransport::new()
+            .route(Method::GET, "/clip/v2/resource/room", rooms())
+            .route(Method::GET, "/clip/v2/resource/room/room-1", room)
+            .route(Method::PUT, "/clip/v2/resource/room/room-1", json!({ "errors": [], "data": [{ "rid": "room-1", "rtype": "room" }] })));
+        let living_room = Room::list_rooms(&bridge).await.unwrap().remove(0);
+
+        living_room.add_children(vec![ResourceRef::new("device-3", "device")]).on(&bridge).await.unwrap();
+        let put = transport.requests().pop().unwrap();
+        assert_eq!(put.body, Some(json!({ "children": [{ "rid": "device-1", "rtype": "device" }, { "rid": "device-3", "rtype": "device" }] })));
+
+        let taken = living_room.add_children(vec![ResourceRef::new("device-2", "device")]).on(&bridge).await;
+        assert!(matches!(taken, Err(HueError::InvalidMembership { .. })));
+        let light = living_room.add_children(vec![ResourceRef::new("light-1", "light")]).on(&bridge).await;
+        assert!(matches!(light, Err(HueError::InvalidMembership { .. })));
+        let missing = living_room.remove_children(vec![ResourceRef::new("device-2", "device")]).on(&bridge).await;
+        assert!(matches!(missing, Err(HueError::InvalidMembership { .. })));
+    }
+
+    #[tokio::test]
+    async fn creates_zone() {
+        let (bridge, transport) = memory_bridge(MemoryTransport::new()
+            .route(Method::POST, "/clip/v2/resource/zone", json!({ "errors": [], "data": [{ "rid": "zone-1", "rtype": "zone" }] })));
+
+        let zone = Room::create(RoomKind::Zone, "Reading corner", "reading").on(&bridge).await.unwrap();
+        assert_eq!(zone, ResourceRef::new("zone-1", "zone"));
+        assert_eq!(transport.requests()[0].body.as_ref().unwrap()["metadata"]["name"], "Reading corner");
+    }
+}
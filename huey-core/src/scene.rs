@@ -0,0 +1,96 @@
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::{ApiVersion, HueError, HueBridge, ResourceRef, v1};
+use crate::resource::{self, Resource};
+
+#[derive(Debug, Clone, Deserialize)]
+struct SceneData {
+    id: String,
+    metadata: SceneMetadata,
+    group: ResourceRef,
+    #[serde(default)]
+    actions: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct SceneMetadata {
+    name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Scene {
+    pub id: String,
+    pub name: String,
+    // The room or zone the scene belongs to.
+    pub group: ResourceRef,
+    pub actions: Vec<Value>,
+    // Fields not covered above, such as the palette and speed.
+    pub extras: Map<String, Value>,
+}
+
+impl Scene {
+    pub async fn list_scenes(bridge: &HueBridge) -> Result<Vec<Scene>, HueError> {
+        if bridge.api_version().await? == ApiVersion::V1 {
+            return v1::list_scenes(bridge).await;
+        }
+
+        resource::list_as::<Scene>(bridge).await
+    }
+
+    pub fn recall(&self) -> SceneTransaction {
+        Scene::recall_id(self.id.clone())
+    }
+
+    pub fn recall_id(scene_id: String) -> SceneTransaction {
+        SceneTransaction {
+            scene_id,
+            body: json!({ "recall": { "action": "active" } })
+        }
+    }
+}
+
+impl Resource for Scene {
+    const RTYPE: &'static str = "scene";
+
+    fn from_json(value: &Value) -> Option<Scene> {
+        let scene = SceneData::deserialize(value).ok()?;
+        let mut scene = Scene {
+            id: scene.id,
+            name: scene.metadata.name,
+            group: scene.group,
+            actions: scene.actions,
+            extras: Map::new(),
+        };
+        scene.extras = resource::unknown_fields(value, &scene.to_json());
+        Some(scene)
+    }
+
+    fn to_json(&self) -> Value {
+        let known = json!({
+            "id": self.id,
+            "type": Scene::RTYPE,
+            "metadata": { "name": self.name },
+            "group": self.group,
+            "actions": self.actions
+        });
+        resource::with_extras(known, &self.extras)
+    }
+}
+
+#[derive(Debug)]
+pub struct SceneTransaction {
+    scene_id: String,
+    body: Value
+}
+
+impl SceneTransaction {
+    pub async fn on(&self, bridge: &HueBridge) -> Result<(), HueError> {
+        if bridge.api_version().await? == ApiVersion::V1 {
+            return v1::recall_scene(bridge, &self.scene_id).await;
+        }
+
+        ResourceRef::new(self.scene_id.clone(), Scene::RTYPE).put(bridge, &self.body).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+use serde::{Deserialize, Serialize};
+
+use crate::HueError;
+
+#[cfg(feature = "secret-service")]
+const KEYRING_SERVICE: &str = "huey";
+
+// How application and client keys are protected when written to the credential store.
+#[derive(Clone)]
+pub enum KeyProtection {
+    // Keys are written as is, only use this when nothing else is available.
+    Plaintext,
+    // Keys are encrypted with ChaCha20-Poly1305 using a key derived from the passphrase with Argon2.
+    #[cfg(feature = "encryption")]
+    Passphrase(String),
+    // Keys live in the OS secret store (Secret Service over D-Bus on Linux), the file only references them.
+    #[cfg(feature = "secret-service")]
+    SecretService,
+}
+
+impl KeyProtection {
+    // The most secure backend compiled in, falling back to plain text.
+    #[cfg(feature = "secret-service")]
+    pub fn preferred() -> KeyProtection {
+        KeyProtection::SecretService
+    }
+
+    // Warned about once, stores are loaded with the preferred protection on every save.
+    #[cfg(not(feature = "secret-service"))]
+    pub fn preferred() -> KeyProtection {
+        static WARNING: std::sync::Once = std::sync::Once::new();
+        WARNING.call_once(|| tracing::warn!("built without the secret-service feature, keys are stored in plain text"));
+        KeyProtection::Plaintext
+    }
+}
+
+impl std::fmt::Debug for KeyProtection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyProtection::Plaintext => write!(f, "Plaintext"),
+            #[cfg(feature = "encryption")]
+            KeyProtection::Passphrase(_) => write!(f, "Passphrase(..)"),
+            #[cfg(feature = "secret-service")]
+            KeyProtection::SecretService => write!(f, "SecretService"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedSecret {
+    // All fields are base64 encoded.
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyringSecret {
+    pub service: String,
+    pub account: String,
+}
+
+// A key as written to disk. Plain keys stay a bare string so older store files keep loading.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StoredSecret {
+    Plain(String),
+    Encrypted(EncryptedSecret),
+    Keyring(KeyringSecret),
+}
+
+impl StoredSecret {
+    // `account` names the secret in the OS secret store, e.g. "<bridge id>/application_key".
+    #[cfg_attr(not(feature = "secret-service"), allow(unused_variables))]
+    pub fn seal(secret: &str, account: &str, protection: &KeyProtection) -> Result<StoredSecret, HueError> {
+        match protection {
+            KeyProtection::Plaintext => Ok(StoredSecret::Plain(secret.into())),
+            #[cfg(feature = "encryption")]
+            KeyProtection::Passphrase(passphrase) => encryption::encrypt(secret, passphrase).map(StoredSecret::Encrypted),
+            #[cfg(feature = "secret-service")]
+            KeyProtection::SecretService => {
+                let entry = keyring::Entry::new(KEYRING_SERVICE, account).map_err(keyring_error)?;
+                entry.set_password(secret).map_err(keyring_error)?;
+                Ok(StoredSecret::Keyring(KeyringSecret { service: KEYRING_SERVICE.into(), account: account.into() }))
+            },
+        }
+    }
+
+    // Whether this already holds `secret` as `protection` would store it, so it doesn't need
+    // sealing again. Saves writing the OS secret store every time the store file is saved.
+    pub fn holds(&self, secret: &str, protection: &KeyProtection) -> bool {
+        let same_protection = match (self, protection) {
+            (StoredSecret::Plain(_), KeyProtection::Plaintext) => true,
+            #[cfg(feature = "encryption")]
+            (StoredSecret::Encrypted(_), KeyProtection::Passphrase(_)) => true,
+            #[cfg(feature = "secret-service")]
+            (StoredSecret::Keyring(_), KeyProtection::SecretService) => true,
+            _ => false,
+        };
+        same_protection && self.open(protection).is_ok_and(|stored| stored == secret)
+    }
+
+    // Secrets are opened according to how they were stored, `protection` only supplies the passphrase.
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    pub fn open(&self, protection: &KeyProtection) -> Result<String, HueError> {
+        match self {
+            StoredSecret::Plain(secret) => Ok(secret.clone()),
+            #[cfg(feature = "encryption")]
+            StoredSecret::Encrypted(encrypted) => match protection {
+                KeyProtection::Passphrase(passphrase) => encryption::decrypt(encrypted, passphrase),
+                _ => Err(HueError::SecretStore { msg: "a passphrase is required to open this key".into() }),
+            },
+            #[cfg(feature = "secret-service")]
+            StoredSecret::Keyring(reference) => keyring::Entry::new(&reference.service, &reference.account)
+                .and_then(|entry| entry.get_password())
+                .map_err(keyring_error),
+            #[allow(unreachable_patterns)]
+            _ => Err(HueError::SecretStore { msg: "huey-core was built without support for this key storage".into() }),
+        }
+    }
+
+    // Removes the secret from the OS secret store, a no-op for keys kept in the file.
+    pub fn forget(&self) -> Result<(), HueError> {
+        #[cfg(feature = "secret-service")]
+        if let StoredSecret::Keyring(reference) = self {
+            return keyring::Entry::new(&reference.service, &reference.account)
+                .and_then(|entry| entry.delete_password())
+                .map_err(keyring_error);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "secret-service")]
+fn keyring_error(err: keyring::Error) -> HueError {
+    HueError::SecretStore { msg: err.to_string() }
+}
+
+#[cfg(feature = "encryption")]
+mod encryption {
+    use argon2::Argon2;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use chacha20poly1305::{aead::{Aead, AeadCore, KeyInit, OsRng, rand_core::RngCore}, ChaCha20Poly1305, Key, Nonce};
+
+    use super::EncryptedSecret;
+    use crate::HueError;
+
+    const SALT_LEN: usize = 16;
+
+    fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, HueError> {
+        let mut key = Key::default();
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|err| HueError::SecretStore { msg: err.to_string() })?;
+        Ok(key)
+    }
+
+    pub fn encrypt(secret: &str, passphrase: &str) -> Result<EncryptedSecret, HueError> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher.encrypt(&nonce, secret.as_bytes())
+            .map_err(|_| HueError::SecretStore { msg: "failed to encrypt key".into() })?;
+
+        Ok(EncryptedSecret {
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        })
+    }
+
+    pub fn decrypt(encrypted: &EncryptedSecret, passphrase: &str) -> Result<String, HueError> {
+        let invalid = |_| HueError::SecretStore { msg: "stored key is corrupt".into() };
+        let salt = STANDARD.decode(&encrypted.salt).map_err(invalid)?;
+        let nonce = STANDARD.decode(&encrypted.nonce).map_err(invalid)?;
+        let ciphertext = STANDARD.decode(&encrypted.ciphertext).map_err(invalid)?;
+        if nonce.len() != 12 {
+            return Err(HueError::SecretStore { msg: "stored key is corrupt".into() });
+        }
+
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+        let plaintext = cipher.decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| HueError::SecretStore { msg: "wrong passphrase or corrupt key".into() })?;
+
+        String::from_utf8(plaintext).map_err(|_| HueError::SecretStore { msg: "stored key is corrupt".into() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_keys_stay_strings() {
+        let sealed = StoredSecret::seal("key", "bridge/application_key", &KeyProtection::Plaintext).unwrap();
+        assert_eq!(serde_json::to_value(&sealed).unwrap(), serde_json::json!("key"));
+        assert_eq!(sealed.open(&KeyProtection::Plaintext).unwrap(), "key");
+        assert!(sealed.holds("key", &KeyProtection::Plaintext) && !sealed.holds("new-key", &KeyProtection::Plaintext));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn passphrase_round_trip() {
+        let protection = KeyProtection::Passphrase("correct horse".into());
+        let sealed = StoredSecret::seal("key", "bridge/application_key", &protection).unwrap();
+        assert!(matches!(sealed, StoredSecret::Encrypted(_)));
+
+        let json = serde_json::to_string(&sealed).unwrap();
+        let sealed: StoredSecret = serde_json::from_str(&json).unwrap();
+        assert_eq!(sealed.open(&protection).unwrap(), "key");
+        assert!(sealed.open(&KeyProtection::Passphrase("wrong".into())).is_err());
+        assert!(sealed.open(&KeyProtection::Plaintext).is_err());
+        assert!(sealed.holds("key", &protection));
+        assert!(!sealed.holds("key", &KeyProtection::Plaintext));
+    }
+}
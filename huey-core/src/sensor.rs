@@ -0,0 +1,255 @@
+// Motion, light level and temperature services, such as the three services of a Hue motion
+// sensor. Reading and configuring them needs CLIP v2.
+// The status LED is the exception: CLIP v2 doesn't expose it, it is only reachable through the
+// sensor's v1 config (`/api/<key>/sensors/<id>/config`). Setting it needs the sensor's `id_v1`.
+
+use std::ops::Deref;
+
+use serde_json::{json, Map, Value};
+
+use crate::{ApiVersion, HueError, HueBridge, ResourceRef, v1};
+use crate::resource::{self, Resource};
+
+open_enum! {
+    pub enum SensorKind {
+        Motion => "motion",
+        LightLevel => "light_level",
+        Temperature => "temperature",
+    }
+}
+
+const KINDS: [SensorKind; 3] = [SensorKind::Motion, SensorKind::LightLevel, SensorKind::Temperature];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sensitivity {
+    pub sensitivity: u32,
+    // The bridge accepts 0 up to and including this.
+    pub max: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Sensor {
+    pub id: String,
+    // Legacy v1 path such as "/sensors/12", needed for the status LED.
+    pub id_v1: Option<String>,
+    pub kind: SensorKind,
+    // The device the sensor belongs to.
+    pub owner: ResourceRef,
+    pub enabled: bool,
+    // Fields not covered above, including the latest report.
+    pub extras: Map<String, Value>,
+}
+
+impl Sensor {
+    pub async fn list_sensors(bridge: &HueBridge) -> Result<Vec<Sensor>, HueError> {
+        if bridge.api_version().await? == ApiVersion::V1 {
+            return Err(HueError::Unsupported);
+        }
+
+        let mut sensors = Vec::new();
+        sensors.extend(resource::list_as::<MotionSensor>(bridge).await?.into_iter().map(|sensor| sensor.0));
+        sensors.extend(resource::list_as::<LightLevelSensor>(bridge).await?.into_iter().map(|sensor| sensor.0));
+        sensors.extend(resource::list_as::<TemperatureSensor>(bridge).await?.into_iter().map(|sensor| sensor.0));
+        Ok(sensors)
+    }
+
+    // The kinds share a model, `Resource` is implemented by a wrapper per kind.
+    pub(crate) fn from_json(kind: SensorKind, value: &Value) -> Option<Sensor> {
+        if value["type"].as_str() != Some(kind.as_str()) {
+            return None;
+        }
+
+        let mut sensor = Sensor {
+            id: value["id"].as_str()?.into(),
+            id_v1: value["id_v1"].as_str().map(|id_v1| id_v1.into()),
+            kind,
+            owner: serde_json::from_value(value["owner"].clone()).ok()?,
+            enabled: value["enabled"].as_bool().unwrap_or(true),
+            extras: Map::new(),
+        };
+        sensor.extras = resource::unknown_fields(value, &sensor.to_json());
+        Some(sensor)
+    }
+
+    // For resources of any sensor kind, such as the whole resource tree.
+    pub(crate) fn from_any_kind(value: &Value) -> Option<Sensor> {
+        KINDS.into_iter().find_map(|kind| Sensor::from_json(kind, value))
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut known = json!({
+            "id": self.id,
+            "type": self.kind,
+            "owner": self.owner,
+            "enabled": self.enabled
+        });
+        if let Some(id_v1) = &self.id_v1 {
+            known["id_v1"] = json!(id_v1);
+        }
+        resource::with_extras(known, &self.extras)
+    }
+
+    // A value from the latest report, e.g. `motion.motion_report.motion`. `None` while the sensor
+    // is disabled or hasn't reported yet.
+    fn report(&self, field: &str, value: &str) -> Option<&Value> {
+        self.extras.get(field)?[format!("{}_report", value)].get(value)
+    }
+
+    pub fn motion(&self) -> Option<bool> {
+        self.report("motion", "motion")?.as_bool()
+    }
+
+    // In lux, the bridge reports 10000 * log10(lux) + 1.
+    pub fn light_level(&self) -> Option<f64> {
+        let light_level = self.report("light", "light_level")?.as_f64()?;
+        Some(10f64.powf((light_level - 1.0) / 10000.0))
+    }
+
+    // In degrees Celsius.
+    pub fn temperature(&self) -> Option<f64> {
+        self.report("temperature", "temperature")?.as_f64()
+    }
+
+    // Only motion sensors on recent firmware report a sensitivity.
+    pub fn sensitivity(&self) -> Option<Sensitivity> {
+        let sensitivity = self.extras.get("sensitivity")?;
+        Some(Sensitivity {
+            sensitivity: sensitivity["sensitivity"].as_u64()? as u32,
+            max: sensitivity["sensitivity_max"].as_u64()? as u32,
+        })
+    }
+
+    pub fn set_enabled(&self, enabled: bool) -> SensorTransaction {
+        self.transaction(SensorChange::Put(json!({ "enabled": enabled })))
+    }
+
+    pub fn set_sensitivity(&self, sensitivity: u32) -> Result<SensorTransaction, HueError> {
+        let Some(current) = self.sensitivity() else { return Err(HueError::Unsupported); };
+        if sensitivity > current.max {
+            return Err(HueError::InvalidData { msg: format!("sensitivity must be 0-{}", current.max) });
+        }
+        Ok(self.transaction(SensorChange::Put(json!({ "sensitivity": { "sensitivity": sensitivity } }))))
+    }
+
+    // The status LED belongs to the device, but is set through the sensor's v1 config. It lights
+    // up on motion or when the sensor is being set up, depending on the model.
+    pub fn set_led_indication(&self, enabled: bool) -> Result<SensorTransaction, HueError> {
+        let id_v1 = self.id_v1.clone().ok_or(HueError::Unsupported)?;
+        Ok(self.transaction(SensorChange::LedIndication { id_v1, enabled }))
+    }
+
+    fn transaction(&self, change: SensorChange) -> SensorTransaction {
+        SensorTransaction { sensor: ResourceRef::new(self.id.clone(), self.kind.as_str()), change }
+    }
+}
+
+macro_rules! sensor_resource {
+    ($name:ident, $kind:ident, $rtype:literal) => {
+        #[derive(Debug, Clone)]
+        pub struct $name(pub Sensor);
+
+        impl Resource for $name {
+            const RTYPE: &'static str = $rtype;
+
+            fn from_json(value: &Value) -> Option<$name> {
+                Sensor::from_json(SensorKind::$kind, value).map($name)
+            }
+
+            fn to_json(&self) -> Value {
+                self.0.to_json()
+            }
+        }
+
+        impl Deref for $name {
+            type Target = Sensor;
+
+            fn deref(&self) -> &Sensor {
+                &self.0
+            }
+        }
+    };
+}
+
+sensor_resource!(MotionSensor, Motion, "motion");
+sensor_resource!(LightLevelSensor, LightLevel, "light_level");
+sensor_resource!(TemperatureSensor, Temperature, "temperature");
+
+#[derive(Debug)]
+enum SensorChange {
+    Put(Value),
+    LedIndication { id_v1: String, enabled: bool },
+}
+
+#[derive(Debug)]
+pub struct SensorTransaction {
+    sensor: ResourceRef,
+    change: SensorChange,
+}
+
+impl SensorTransaction {
+    pub async fn on(&self, bridge: &HueBridge) -> Result<(), HueError> {
+        match &self.change {
+            SensorChange::Put(body) => { self.sensor.put(bridge, body).await?; },
+            SensorChange::LedIndication { id_v1, enabled } => v1::put_sensor_config(bridge, id_v1, "ledindication", json!(enabled)).await?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Method;
+
+    use super::*;
+    use crate::tests::memory_bridge;
+    use crate::transport::MemoryTransport;
+
+    fn motion() -> Value {
+        json!({
+            "id": "motion-1",
+            "id_v1": "/sensors/12",
+            "type": "motion",
+            "owner": { "rid": "device-1", "rtype": "device" },
+            "enabled": true,
+            "motion": { "motion_report": { "changed": "2023-03-01T10:00:00.000Z", "motion": true } },
+            "sensitivity": { "status": "set", "sensitivity": 2, "sensitivity_max": 4 }
+        })
+    }
+
+    #[test]
+    fn reads_sensor() {
+        let sensor = MotionSensor::from_json(&motion()).unwrap();
+        assert_eq!(sensor.kind, SensorKind::Motion);
+        assert_eq!(sensor.to_json(), motion());
+        assert!(TemperatureSensor::from_json(&motion()).is_none());
+        assert_eq!(sensor.motion(), Some(true));
+        assert_eq!(sensor.temperature(), None);
+        assert_eq!(sensor.sensitivity(), Some(Sensitivity { sensitivity: 2, max: 4 }));
+
+        let light_level = LightLevelSensor::from_json(&json!({
+            "id": "light-level-1",
+            "type": "light_level",
+            "owner": { "rid": "device-1", "rtype": "device" },
+            "enabled": true,
+            "light": { "light_level_report": { "light_level": 20001 } }
+        })).unwrap();
+        assert_eq!(light_level.light_level().map(|lux| lux.round()), Some(100.0));
+    }
+
+    #[tokio::test]
+    async fn configures_sensor() {
+        let (bridge, transport) = memory_bridge(MemoryTransport::new()
+            .route(Method::GET, "/clip/v2/resource/motion/motion-1", json!({ "errors": [], "data": [motion()] }))
+            .route(Method::PUT, "/clip/v2/resource/motion/motion-1", json!({ "errors": [], "data": [{ "rid": "motion-1", "rtype": "motion" }] }))
+            .route(Method::GET, "/api/test-key/sensors/12", json!({ "config": { "on": true, "ledindication": false } }))
+            .route(Method::PUT, "/api/test-key/sensors/12/config", json!([{ "success": { "/sensors/12/config/ledindication": true } }])));
+        let sensor = ResourceRef::new("motion-1", MotionSensor::RTYPE).get_as::<MotionSensor>(&bridge).await.unwrap();
+
+        assert!(matches!(sensor.set_sensitivity(5), Err(HueError::InvalidData { .. })));
+        sensor.set_sensitivity(4).unwrap().on(&bridge).await.unwrap();
+        assert_eq!(transport.requests()[1].body, Some(json!({ "sensitivity": { "sensitivity": 4 } })));
+
+        sensor.set_led_indication(true).unwrap().on(&bridge).await.unwrap();
+        assert_eq!(transport.requests()[3].body, Some(json!({ "ledindication": true })));
+    }
+}
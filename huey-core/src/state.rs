@@ -0,0 +1,308 @@
+// Local mirror of every CLIP v2 resource on a bridge. It is loaded once and then kept current
+// by the bridge's event stream, so frontends can read from it instead of polling.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::StreamExt;
+use serde_json::Value;
+use tokio::sync::watch;
+
+use crate::{ApiVersion, HueError, HueBridge, ResourceRef};
+use crate::graph::ResourceGraph;
+use crate::light::Light;
+use crate::resource::{self, Resource};
+use crate::room::{Room, RoomKind};
+use crate::scene::Scene;
+use crate::sensor::Sensor;
+
+const EVENT_STREAM: &str = "/eventstream/clip/v2";
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// Every resource on the bridge at one point in time, keyed by id.
+#[derive(Debug, Clone, Default)]
+pub struct StateSnapshot {
+    resources: BTreeMap<String, Value>,
+    revision: u64,
+}
+
+impl StateSnapshot {
+    // Increases whenever the snapshot changes.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn get(&self, rid: &str) -> Option<&Value> {
+        self.resources.get(rid)
+    }
+
+    // Looks up the resource a reference points to, e.g. a light's owner device.
+    pub fn resolve(&self, reference: &ResourceRef) -> Option<&Value> {
+        self.get(&reference.rid)
+            .filter(|resource| resource["type"].as_str() == Some(reference.rtype.as_str()))
+    }
+
+    pub fn resources<'a>(&'a self, rtype: &'a str) -> impl Iterator<Item = &'a Value> {
+        self.resources.values().filter(move |resource| resource["type"].as_str() == Some(rtype))
+    }
+
+    pub fn lights(&self) -> Vec<Light> {
+        self.resources(Light::RTYPE).filter_map(Light::from_json).collect()
+    }
+
+    pub fn light(&self, light_id: &str) -> Option<Light> {
+        self.resolve(&ResourceRef::new(light_id, Light::RTYPE)).and_then(Light::from_json)
+    }
+
+    pub fn rooms(&self) -> Vec<Room> {
+        self.resources("room").filter_map(|room| Room::from_json(RoomKind::Room, room)).collect()
+    }
+
+    pub fn zones(&self) -> Vec<Room> {
+        self.resources("zone").filter_map(|zone| Room::from_json(RoomKind::Zone, zone)).collect()
+    }
+
+    pub fn scenes(&self) -> Vec<Scene> {
+        self.resources(Scene::RTYPE).filter_map(Scene::from_json).collect()
+    }
+
+    // Motion, light level and temperature services.
+    pub fn sensors(&self) -> Vec<Sensor> {
+        self.resources.values().filter_map(Sensor::from_any_kind).collect()
+    }
+
+    pub fn graph(&self) -> ResourceGraph {
+        ResourceGraph::from_resources(self.resources.values())
+    }
+
+    pub fn lights_in(&self, room: &Room) -> Vec<Light> {
+        self.graph()
+            .lights_in(&ResourceRef::new(room.id.clone(), room.kind.rtype()))
+            .iter()
+            .filter_map(|light| self.light(&light.rid))
+            .collect()
+    }
+
+    // Applies one event container: `{ "type": "update" | "add" | "delete", "data": [...] }`.
+    // Returns whether any resource changed, unknown event types and no-op updates don't count.
+    fn apply(&mut self, container: &Value) -> bool {
+        let Some(resources) = container["data"].as_array() else { return false; };
+
+        let mut changed = false;
+        for resource in resources {
+            let Some(rid) = resource["id"].as_str() else { continue; };
+            changed |= match container["type"].as_str() {
+                Some("add") => self.resources.insert(rid.into(), resource.clone()).as_ref() != Some(resource),
+                Some("delete") => self.resources.remove(rid).is_some(),
+                // Updates only carry the fields that changed.
+                Some("update") => match self.resources.get_mut(rid) {
+                    Some(existing) => {
+                        let previous = existing.clone();
+                        resource::merge(existing, resource);
+                        *existing != previous
+                    },
+                    None => false,
+                },
+                _ => false,
+            };
+        }
+        if changed {
+            self.revision += 1;
+        }
+        changed
+    }
+}
+
+// Splits the event stream into the json payload of each server-sent event.
+#[derive(Debug, Default)]
+struct EventStreamParser {
+    buffer: Vec<u8>,
+}
+
+impl EventStreamParser {
+    fn push(&mut self, chunk: &[u8]) -> Vec<Value> {
+        self.buffer.extend(chunk.iter().filter(|byte| **byte != b'\r'));
+
+        let mut payloads = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|window| window == b"\n\n") {
+            let event = self.buffer.drain(..end + 2).collect::<Vec<u8>>();
+            let data = String::from_utf8_lossy(&event)
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.trim_start())
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            // Comments such as ": hi" have no data.
+            if let Ok(payload) = serde_json::from_str(&data) {
+                payloads.push(payload);
+            }
+        }
+        payloads
+    }
+}
+
+// Clones share the same snapshot.
+#[derive(Debug, Clone)]
+pub struct HueState {
+    bridge: HueBridge,
+    snapshot: Arc<watch::Sender<Arc<StateSnapshot>>>,
+}
+
+impl HueState {
+    // Loads every resource, the event stream is only available on CLIP v2 bridges.
+    pub async fn load(bridge: HueBridge) -> Result<HueState, HueError> {
+        if bridge.api_version().await? == ApiVersion::V1 {
+            return Err(HueError::Unsupported);
+        }
+
+        let (snapshot, _) = watch::channel(Arc::new(StateSnapshot::default()));
+        let state = HueState { bridge, snapshot: Arc::new(snapshot) };
+        state.refresh().await?;
+        Ok(state)
+    }
+
+    pub fn bridge(&self) -> &HueBridge {
+        &self.bridge
+    }
+
+    pub fn snapshot(&self) -> Arc<StateSnapshot> {
+        self.snapshot.borrow().clone()
+    }
+
+    // The receiver is notified after every change, e.g. `while receiver.changed().await.is_ok()`.
+    pub fn subscribe(&self) -> watch::Receiver<Arc<StateSnapshot>> {
+        self.snapshot.subscribe()
+    }
+
+    // Reloads every resource, used after the event stream was interrupted and events may have been missed.
+    pub async fn refresh(&self) -> Result<(), HueError> {
+        let resources = resource::list(&self.bridge, "").await?
+            .into_iter()
+            .filter_map(|resource| Some((resource["id"].as_str()?.to_string(), resource)))
+            .collect::<BTreeMap<_, _>>();
+        tracing::debug!(resources = resources.len(), "loaded bridge state");
+
+        self.snapshot.send_modify(|snapshot| {
+            let revision = snapshot.revision + 1;
+            *snapshot = Arc::new(StateSnapshot { resources, revision });
+        });
+        Ok(())
+    }
+
+    // Applies an event stream message, a json array of event containers.
+    pub fn apply(&self, message: &Value) {
+        let Some(containers) = message.as_array() else { return; };
+        if containers.is_empty() {
+            return;
+        }
+
+        // Watchers are only woken when something changed.
+        self.snapshot.send_if_modified(|snapshot| {
+            let snapshot = Arc::make_mut(snapshot);
+            containers.iter().fold(false, |changed, container| snapshot.apply(container) | changed)
+        });
+    }
+
+    // Applies events until the bridge closes the event stream.
+    #[tracing::instrument(name = "event_stream", skip_all, fields(bridge_ip = %self.bridge.bridge_ip()))]
+    pub async fn listen(&self) -> Result<(), HueError> {
+        let mut stream = self.bridge.stream(EVENT_STREAM).await?;
+        let mut parser = EventStreamParser::default();
+        tracing::info!("event stream connected");
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.inspect_err(|err| tracing::warn!(error = %err, "event stream failed"))?;
+            for message in parser.push(&chunk) {
+                tracing::trace!(events = message.as_array().map(|events| events.len()).unwrap_or(0), "applying events");
+                self.apply(&message);
+            }
+        }
+
+        tracing::info!("event stream closed");
+        Ok(())
+    }
+
+    // Keeps the state in sync for as long as the future is polled, reconnecting and reloading
+    // everything whenever the event stream drops. Usually spawned as a background task.
+    pub async fn run(&self) {
+        loop {
+            if let Err(err) = self.listen().await {
+                tracing::warn!(error = %err, "couldn't listen to event stream");
+            }
+
+            tracing::debug!(delay = ?RECONNECT_DELAY, "reconnecting event stream");
+            tokio::time::sleep(RECONNECT_DELAY).await;
+            if let Err(err) = self.refresh().await {
+                tracing::warn!(error = %err, "couldn't reload bridge state");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::transport::MemoryTransport;
+
+    fn resources() -> Value {
+        let mut lights = serde_json::from_str::<Value>(include_str!("../example_json/lights.json")).unwrap();
+        let mut data = lights["data"].take().as_array().unwrap().clone();
+        data.push(json!({
+            "id": "room-1",
+            "type": "room",
+            "metadata": { "name": "Living room", "archetype": "living_room" },
+            "children": [{ "rid": "ea6e48b4-f82d-4700-a3a8-5504ecd07776", "rtype": "device" }],
+            "services": []
+        }));
+        data.push(json!({
+            "id": "ea6e48b4-f82d-4700-a3a8-5504ecd07776",
+            "type": "device",
+            "services": [{ "rid": "afafbcfd-0807-49bc-aa72-289f5ffe4005", "rtype": "light" }]
+        }));
+        json!({ "errors": [], "data": data })
+    }
+
+    #[test]
+    fn parses_split_events() {
+        let mut parser = EventStreamParser::default();
+        assert!(parser.push(b": hi\n\nid: 1:0\ndata: [{\"type\":").is_empty());
+
+        let payloads = parser.push(b"\"update\",\"data\":[]}]\n\n");
+        assert_eq!(payloads, vec![json!([{ "type": "update", "data": [] }])]);
+    }
+
+    #[tokio::test]
+    async fn applies_event_stream() {
+        let update = "id: 1:0\ndata: [{\"type\":\"update\",\"data\":[{\"id\":\"afafbcfd-0807-49bc-aa72-289f5ffe4005\",\"type\":\"light\",\"on\":{\"on\":false}}]}]\n\n";
+        let transport = MemoryTransport::new()
+            .route(Method::GET, "/clip/v2/resource", resources())
+            .stream_route(EVENT_STREAM, vec![update.as_bytes()[..40].to_vec(), update.as_bytes()[40..].to_vec()]);
+        let bridge = HueBridge::new("10.0.0.2".into(), "test-key".into())
+            .with_api_version(ApiVersion::V2)
+            .with_transport(Arc::new(transport));
+
+        let state = HueState::load(bridge).await.unwrap();
+        let mut receiver = state.subscribe();
+        let room = state.snapshot().rooms().remove(0);
+        let lamp = state.snapshot().lights_in(&room).remove(0);
+        assert!(lamp.is_on);
+
+        state.apply(&json!([{ "type": "ping", "data": [] }, { "type": "update", "data": [{ "id": lamp.id, "type": "light", "on": { "on": true } }] }]));
+        assert!(!receiver.has_changed().unwrap());
+        let revision = state.snapshot().revision();
+
+        state.listen().await.unwrap();
+        assert!(receiver.has_changed().unwrap());
+        assert_eq!(receiver.borrow().revision(), revision + 1);
+
+        let snapshot = receiver.borrow_and_update().clone();
+        let lamp = snapshot.light(&lamp.id).unwrap();
+        assert!(!lamp.is_on);
+        assert_eq!(lamp.name, "Lamp");
+    }
+}
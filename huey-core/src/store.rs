@@ -0,0 +1,256 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{HueError, HueBridge};
+use crate::secret::{KeyProtection, StoredSecret};
+
+const STORE_DIR: &str = "huey";
+const STORE_FILE: &str = "bridges.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredBridge {
+    pub bridge_ip: String,
+    pub application_key: StoredSecret,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<StoredSecret>,
+    // Seconds since the unix epoch.
+    pub last_seen: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoreFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default: Option<String>,
+    #[serde(default)]
+    bridges: BTreeMap<String, StoredBridge>,
+}
+
+// Paired bridges shared by every frontend, keyed by bridge id.
+// Lives in $XDG_CONFIG_HOME/huey/bridges.json (or the platform equivalent).
+#[derive(Debug)]
+pub struct CredentialStore {
+    path: PathBuf,
+    file: StoreFile,
+    protection: KeyProtection,
+}
+
+impl CredentialStore {
+    pub fn default_path() -> Result<PathBuf, HueError> {
+        let config_dir = dirs::config_dir().ok_or(HueError::ConfigDir)?;
+        Ok(config_dir.join(STORE_DIR).join(STORE_FILE))
+    }
+
+    pub fn load() -> Result<CredentialStore, HueError> {
+        CredentialStore::load_from(CredentialStore::default_path()?)
+    }
+
+    // A missing file is treated as an empty store, it is created on the first save.
+    // Keys are protected with `KeyProtection::preferred()` unless changed with `with_protection`.
+    pub fn load_from(path: impl Into<PathBuf>) -> Result<CredentialStore, HueError> {
+        let path = path.into();
+        let file = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => StoreFile::default(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(CredentialStore { path, file, protection: KeyProtection::preferred() })
+    }
+
+    pub fn with_protection(mut self, protection: KeyProtection) -> CredentialStore {
+        self.protection = protection;
+        self
+    }
+
+    pub fn set_protection(&mut self, protection: KeyProtection) {
+        self.protection = protection;
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn save(&self) -> Result<(), HueError> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Write to a sibling file first so a crash never leaves a half written store.
+        let tmp_path = self.path.with_extension("json.tmp");
+        let mut tmp_file = create_private(&tmp_path)?;
+        tmp_file.write_all(serde_json::to_string_pretty(&self.file)?.as_bytes())?;
+        tmp_file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        Ok(())
+    }
+
+    pub fn bridges(&self) -> impl Iterator<Item = (&String, &StoredBridge)> {
+        self.file.bridges.iter()
+    }
+
+    pub fn get(&self, bridge_id: &str) -> Option<&StoredBridge> {
+        self.file.bridges.get(&normalize_id(bridge_id))
+    }
+
+    pub fn bridge(&self, bridge_id: &str) -> Result<Option<HueBridge>, HueError> {
+        let bridge_id = normalize_id(bridge_id);
+        let Some(stored) = self.file.bridges.get(&bridge_id) else { return Ok(None); };
+
+        let application_key = stored.application_key.open(&self.protection)?;
+        let mut bridge = HueBridge::new(stored.bridge_ip.clone(), application_key)
+            .with_bridge_id(bridge_id);
+        if let Some(client_key) = &stored.client_key {
+            bridge = bridge.with_client_key(client_key.open(&self.protection)?);
+        }
+        Ok(Some(bridge))
+    }
+
+    pub fn default_id(&self) -> Option<&str> {
+        self.file.default.as_deref()
+    }
+
+    pub fn default_bridge(&self) -> Result<Option<HueBridge>, HueError> {
+        match self.default_id() {
+            Some(bridge_id) => self.bridge(bridge_id),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_default(&mut self, bridge_id: &str) -> Result<(), HueError> {
+        let bridge_id = normalize_id(bridge_id);
+        if !self.file.bridges.contains_key(&bridge_id) {
+            return Err(HueError::InvalidData { msg: format!("unknown bridge {}", bridge_id) });
+        }
+
+        self.file.default = Some(bridge_id);
+        Ok(())
+    }
+
+    // Records a paired bridge, the first bridge stored becomes the default.
+    pub fn remember(&mut self, bridge: &HueBridge) -> Result<(), HueError> {
+        let bridge_id = bridge.bridge_id.as_deref()
+            .map(normalize_id)
+            .ok_or(HueError::InvalidData { msg: "bridge id is required to store a bridge".into() })?;
+
+        // Unchanged keys are kept as stored rather than sealed again.
+        let stored = self.file.bridges.get(&bridge_id);
+        let seal = |existing: Option<&StoredSecret>, secret: &str, name: &str| match existing {
+            Some(existing) if existing.holds(secret, &self.protection) => Ok(existing.clone()),
+            _ => StoredSecret::seal(secret, &format!("{}/{}", bridge_id, name), &self.protection),
+        };
+        let application_key = seal(stored.map(|stored| &stored.application_key), &bridge.username, "application_key")?;
+        let client_key = bridge.client_key.as_deref()
+            .map(|client_key| seal(stored.and_then(|stored| stored.client_key.as_ref()), client_key, "client_key"))
+            .transpose()?;
+
+        self.file.bridges.insert(bridge_id.clone(), StoredBridge {
+            bridge_ip: bridge.bridge_ip(),
+            application_key,
+            client_key,
+            last_seen: now(),
+        });
+        self.file.default.get_or_insert(bridge_id);
+
+        Ok(())
+    }
+
+    // Keeps the stored ip current after `BridgeEvent::IpChanged`.
+    pub fn update_ip(&mut self, bridge_id: &str, bridge_ip: String) {
+        if let Some(stored) = self.file.bridges.get_mut(&normalize_id(bridge_id)) {
+            stored.bridge_ip = bridge_ip;
+            stored.last_seen = now();
+        }
+    }
+
+    pub fn touch(&mut self, bridge_id: &str) {
+        if let Some(stored) = self.file.bridges.get_mut(&normalize_id(bridge_id)) {
+            stored.last_seen = now();
+        }
+    }
+
+    // Also deletes the bridge's keys from the OS secret store when they were kept there. The keys
+    // go first, if that fails the bridge is kept so its secrets aren't left behind unreferenced.
+    pub fn remove(&mut self, bridge_id: &str) -> Result<Option<StoredBridge>, HueError> {
+        let bridge_id = normalize_id(bridge_id);
+        if let Some(stored) = self.file.bridges.get(&bridge_id) {
+            stored.application_key.forget()?;
+            if let Some(client_key) = &stored.client_key {
+                client_key.forget()?;
+            }
+        }
+
+        let removed = self.file.bridges.remove(&bridge_id);
+        if self.file.default.as_deref() == Some(bridge_id.as_str()) {
+            self.file.default = self.file.bridges.keys().next().cloned();
+        }
+
+        Ok(removed)
+    }
+}
+
+// Discovery reports ids in lower case while the bridge config uses upper case.
+pub fn normalize_id(bridge_id: &str) -> String {
+    bridge_id.to_lowercase()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+// Only readable by the current user from the start, so the keys are never briefly world readable.
+// A leftover file from an earlier crash is removed first, its permissions would be kept otherwise.
+fn create_private(path: &Path) -> std::io::Result<fs::File> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+        _ => {},
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remember_and_reload() {
+        let path = std::env::temp_dir().join(format!("huey-store-{}.json", uuid::Uuid::new_v4()));
+
+        let mut store = CredentialStore::load_from(&path).unwrap().with_protection(KeyProtection::Plaintext);
+        assert!(store.default_bridge().unwrap().is_none());
+
+        let first = HueBridge::new("10.0.0.2".into(), "first-key".into()).with_bridge_id("ECB5FAFFFE000001".into());
+        let second = HueBridge::new("10.0.0.3".into(), "second-key".into()).with_bridge_id("ecb5fafffe000002".into());
+        store.remember(&first).unwrap();
+        store.remember(&second).unwrap();
+        store.save().unwrap();
+
+        let mut store = CredentialStore::load_from(&path).unwrap().with_protection(KeyProtection::Plaintext);
+        assert_eq!(store.bridges().count(), 2);
+        assert_eq!(store.default_bridge().unwrap().unwrap().username, "first-key");
+
+        store.set_default("ECB5FAFFFE000002").unwrap();
+        assert_eq!(store.default_bridge().unwrap().unwrap().bridge_ip(), "10.0.0.3");
+
+        #[cfg(unix)]
+        assert_eq!(std::os::unix::fs::PermissionsExt::mode(&fs::metadata(&path).unwrap().permissions()) & 0o777, 0o600);
+
+        store.remove("ecb5fafffe000002").unwrap();
+        assert_eq!(store.default_id(), Some("ecb5fafffe000001"));
+
+        fs::remove_file(path).unwrap();
+    }
+}
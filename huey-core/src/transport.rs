@@ -0,0 +1,452 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+use reqwest::{ClientBuilder, Method};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::HueError;
+use crate::config::ClientConfig;
+
+pub type ByteStream = BoxStream<'static, Result<Vec<u8>, HueError>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HueRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Value>,
+}
+
+impl HueRequest {
+    pub fn new(method: Method, url: impl Into<String>) -> HueRequest {
+        HueRequest { method, url: url.into(), headers: Vec::new(), body: None }
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> HueRequest {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn body(mut self, body: Value) -> HueRequest {
+        self.body = Some(body);
+        self
+    }
+
+    // The url without scheme and host, e.g. "/clip/v2/resource/light".
+    pub fn path(&self) -> &str {
+        let without_scheme = self.url.split_once("://").map(|(_, rest)| rest).unwrap_or(&self.url);
+        without_scheme.find('/').map(|index| &without_scheme[index..]).unwrap_or("/")
+    }
+
+    pub fn host(&self) -> &str {
+        let without_scheme = self.url.split_once("://").map(|(_, rest)| rest).unwrap_or("");
+        without_scheme.split('/').next().unwrap_or("")
+    }
+
+    // The path with the application keys of v1 paths replaced, safe to log or store. This
+    // includes the key being revoked in "/config/whitelist/<key>".
+    pub fn redacted_path(&self) -> String {
+        match v1_username(self.path()) {
+            Some((_, "")) => format!("/api/{}", REDACTED_KEY),
+            Some((_, rest)) if rest.starts_with("config/whitelist/") => format!("/api/{}/config/whitelist/{}", REDACTED_KEY, REDACTED_KEY),
+            Some((_, rest)) => format!("/api/{}/{}", REDACTED_KEY, rest),
+            None => self.path().to_string(),
+        }
+    }
+}
+
+pub const REDACTED_KEY: &str = "<application-key>";
+
+// Splits "/api/<username>/lights" into the username and the rest of the path.
+// "/api/0/config" is the unauthenticated config and has no username.
+pub(crate) fn v1_username(path: &str) -> Option<(&str, &str)> {
+    let rest = path.strip_prefix("/api/")?;
+    let (username, rest) = rest.split_once('/').unwrap_or((rest, ""));
+    (!username.is_empty() && username != "0").then_some((username, rest))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HueResponse {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl HueResponse {
+    pub fn ok(body: Value) -> HueResponse {
+        HueResponse { status: 200, body }
+    }
+}
+
+// Everything huey-core sends to a bridge or the discovery service goes through a transport.
+// A transport should return `HueError::Unreachable` when the host can't be reached so the
+// bridge can be rediscovered, and only when the request wasn't sent. A request that may have
+// reached the host before failing, e.g. one that timed out, should give `HueError::Timeout`.
+#[async_trait]
+pub trait HueTransport: fmt::Debug + Send + Sync {
+    async fn send(&self, request: HueRequest) -> Result<HueResponse, HueError>;
+
+    // Long lived responses such as the event stream, delivered chunk by chunk.
+    async fn stream(&self, request: HueRequest) -> Result<ByteStream, HueError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+    // Without the request timeout, so long lived streams aren't cut off.
+    stream_client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    pub fn new() -> Result<ReqwestTransport, HueError> {
+        ReqwestTransport::with_config(&ClientConfig::default())
+    }
+
+    // Bridges use a self signed certificate.
+    pub fn with_config(config: &ClientConfig) -> Result<ReqwestTransport, HueError> {
+        let builder = || ClientBuilder::new()
+            .danger_accept_invalid_certs(true)
+            .connect_timeout(config.connect_timeout);
+
+        let client = builder()
+            .timeout(config.request_timeout)
+            .build()
+            .map_err(|_| { HueError::ClientCreate })?;
+        let stream_client = builder()
+            .build()
+            .map_err(|_| { HueError::ClientCreate })?;
+
+        Ok(ReqwestTransport { client, stream_client })
+    }
+
+    pub fn from_client(client: reqwest::Client) -> ReqwestTransport {
+        ReqwestTransport { client: client.clone(), stream_client: client }
+    }
+
+    fn build(&self, client: &reqwest::Client, request: HueRequest) -> reqwest::RequestBuilder {
+        let mut builder = client.request(request.method, request.url);
+        for (name, value) in request.headers {
+            builder = builder.header(name, value);
+        }
+        if let Some(body) = &request.body {
+            builder = builder.json(body);
+        }
+        builder
+    }
+}
+
+// Connect errors, including DNS failures and connect timeouts, happen before anything is sent.
+fn map_reqwest_error(err: reqwest::Error) -> HueError {
+    if err.is_connect() {
+        HueError::Unreachable { msg: err.to_string() }
+    } else if err.is_timeout() {
+        HueError::Timeout
+    } else {
+        HueError::Request(err)
+    }
+}
+
+#[async_trait]
+impl HueTransport for ReqwestTransport {
+    async fn send(&self, request: HueRequest) -> Result<HueResponse, HueError> {
+        let response = self.build(&self.client, request).send().await.map_err(map_reqwest_error)?;
+        let status = response.status().as_u16();
+        let body = response.json::<Value>().await.map_err(map_reqwest_error)?;
+        Ok(HueResponse { status, body })
+    }
+
+    async fn stream(&self, request: HueRequest) -> Result<ByteStream, HueError> {
+        let response = self.build(&self.stream_client, request).send().await.map_err(map_reqwest_error)?;
+        let chunks = response.bytes_stream()
+            .map(|chunk| chunk.map(|bytes| bytes.to_vec()).map_err(map_reqwest_error));
+        Ok(chunks.boxed())
+    }
+}
+
+pub(crate) fn default_transport(config: &ClientConfig) -> Arc<dyn HueTransport> {
+    match ReqwestTransport::with_config(config) {
+        Ok(transport) => Arc::new(transport),
+        // Only fails when the TLS backend can't be initialised, surface it on the first request instead.
+        Err(_) => Arc::new(FailingTransport),
+    }
+}
+
+// Stands in for a client that couldn't be created, every request fails with `HueError::ClientCreate`.
+#[derive(Debug)]
+struct FailingTransport;
+
+#[async_trait]
+impl HueTransport for FailingTransport {
+    async fn send(&self, _request: HueRequest) -> Result<HueResponse, HueError> {
+        Err(HueError::ClientCreate)
+    }
+
+    async fn stream(&self, _request: HueRequest) -> Result<ByteStream, HueError> {
+        Err(HueError::ClientCreate)
+    }
+}
+
+type Handler = Box<dyn Fn(&HueRequest) -> Result<HueResponse, HueError> + Send + Sync>;
+
+struct Route {
+    method: Method,
+    // Either a path ("/clip/v2/resource/light") or a full url to also match the host.
+    target: String,
+    responses: VecDeque<HueResponse>,
+}
+
+impl Route {
+    fn matches(&self, request: &HueRequest) -> bool {
+        let target_matches = if self.target.contains("://") {
+            self.target == request.url
+        } else {
+            self.target == request.path()
+        };
+        self.method == request.method && target_matches
+    }
+}
+
+// Serves canned responses without touching the network, for unit tests and simulators.
+// Each route answers with its queued responses in order and keeps repeating the last one.
+// Unmatched requests get a CLIP v2 "not found" error unless a fallback handler is set.
+pub struct MemoryTransport {
+    routes: Mutex<Vec<Route>>,
+    streams: Mutex<Vec<(String, Vec<Vec<u8>>)>>,
+    fallback: Option<Handler>,
+    requests: Mutex<Vec<HueRequest>>,
+}
+
+impl MemoryTransport {
+    pub fn new() -> MemoryTransport {
+        MemoryTransport {
+            routes: Mutex::new(Vec::new()),
+            streams: Mutex::new(Vec::new()),
+            fallback: None,
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn route(self, method: Method, target: impl Into<String>, body: Value) -> MemoryTransport {
+        self.route_response(method, target, HueResponse::ok(body))
+    }
+
+    pub fn route_response(self, method: Method, target: impl Into<String>, response: HueResponse) -> MemoryTransport {
+        let target = target.into();
+        {
+            let mut routes = self.routes.lock().unwrap();
+            match routes.iter_mut().find(|route| route.method == method && route.target == target) {
+                Some(route) => route.responses.push_back(response),
+                None => routes.push(Route { method, target, responses: VecDeque::from([response]) }),
+            }
+        }
+        self
+    }
+
+    pub fn stream_route(self, path: impl Into<String>, chunks: Vec<Vec<u8>>) -> MemoryTransport {
+        self.streams.lock().unwrap().push((path.into(), chunks));
+        self
+    }
+
+    pub fn with_fallback(mut self, handler: impl Fn(&HueRequest) -> Result<HueResponse, HueError> + Send + Sync + 'static) -> MemoryTransport {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    // Every request received so far, in order.
+    pub fn requests(&self) -> Vec<HueRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Default for MemoryTransport {
+    fn default() -> Self {
+        MemoryTransport::new()
+    }
+}
+
+impl fmt::Debug for MemoryTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryTransport")
+            .field("routes", &self.routes.lock().unwrap().len())
+            .field("requests", &self.requests.lock().unwrap().len())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl HueTransport for MemoryTransport {
+    async fn send(&self, request: HueRequest) -> Result<HueResponse, HueError> {
+        self.requests.lock().unwrap().push(request.clone());
+
+        let routed = {
+            let mut routes = self.routes.lock().unwrap();
+            routes.iter_mut()
+                .find(|route| route.matches(&request))
+                .and_then(|route| {
+                    if route.responses.len() > 1 { route.responses.pop_front() } else { route.responses.front().cloned() }
+                })
+        };
+
+        match (routed, &self.fallback) {
+            (Some(response), _) => Ok(response),
+            (None, Some(fallback)) => fallback(&request),
+            (None, None) => Ok(HueResponse {
+                status: 404,
+                body: json!({ "errors": [{ "description": format!("no route for {} {}", request.method, request.path()) }], "data": [] })
+            }),
+        }
+    }
+
+    async fn stream(&self, request: HueRequest) -> Result<ByteStream, HueError> {
+        self.requests.lock().unwrap().push(request.clone());
+
+        let chunks = self.streams.lock().unwrap()
+            .iter()
+            .find(|(path, _)| path == request.path())
+            .map(|(_, chunks)| chunks.clone())
+            .ok_or(HueError::Unreachable { msg: format!("no stream for {}", request.path()) })?;
+
+        Ok(stream::iter(chunks.into_iter().map(Ok)).boxed())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exchange {
+    pub request: HueRequest,
+    pub response: HueResponse,
+}
+
+// Wraps another transport and keeps every request/response pair it sees.
+// Streams are passed through without being recorded.
+#[derive(Debug)]
+pub struct RecordingTransport<T: HueTransport> {
+    inner: T,
+    exchanges: Mutex<Vec<Exchange>>,
+}
+
+impl<T: HueTransport> RecordingTransport<T> {
+    pub fn new(inner: T) -> RecordingTransport<T> {
+        RecordingTransport { inner, exchanges: Mutex::new(Vec::new()) }
+    }
+
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        self.exchanges.lock().unwrap().clone()
+    }
+
+    pub fn take_exchanges(&self) -> Vec<Exchange> {
+        std::mem::take(&mut *self.exchanges.lock().unwrap())
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<T: HueTransport> HueTransport for RecordingTransport<T> {
+    async fn send(&self, request: HueRequest) -> Result<HueResponse, HueError> {
+        let response = self.inner.send(request.clone()).await?;
+        self.exchanges.lock().unwrap().push(Exchange { request, response: response.clone() });
+        Ok(response)
+    }
+
+    async fn stream(&self, request: HueRequest) -> Result<ByteStream, HueError> {
+        self.inner.stream(request).await
+    }
+}
+
+// Sends reads to the wrapped transport but keeps every PUT, POST and DELETE instead of sending it,
+// answering with a success so callers carry on as if the bridge had accepted the change. Pairing
+// fails with `HueError::Unsupported`, a made up application key would be of no use.
+#[derive(Debug)]
+pub struct DryRunTransport {
+    inner: Arc<dyn HueTransport>,
+    withheld: Mutex<Vec<HueRequest>>,
+}
+
+impl DryRunTransport {
+    pub fn new(inner: Arc<dyn HueTransport>) -> DryRunTransport {
+        DryRunTransport { inner, withheld: Mutex::new(Vec::new()) }
+    }
+
+    // Every change that would have been sent, in order.
+    pub fn requests(&self) -> Vec<HueRequest> {
+        self.withheld.lock().unwrap().clone()
+    }
+
+    pub fn take_requests(&self) -> Vec<HueRequest> {
+        std::mem::take(&mut *self.withheld.lock().unwrap())
+    }
+}
+
+// CLIP v2 answers with references to the changed resource, v1 with a list of successes.
+// Created resources get a made up id.
+fn dry_run_response(request: &HueRequest) -> HueResponse {
+    let Some(resource) = request.path().strip_prefix("/clip/v2/resource/") else {
+        return HueResponse::ok(json!([{ "success": {} }]));
+    };
+
+    let (rtype, rid) = match resource.split_once('/') {
+        Some((rtype, rid)) => (rtype.to_string(), rid.to_string()),
+        None => (resource.to_string(), Uuid::new_v4().to_string()),
+    };
+    HueResponse::ok(json!({ "errors": [], "data": [{ "rid": rid, "rtype": rtype }] }))
+}
+
+#[async_trait]
+impl HueTransport for DryRunTransport {
+    async fn send(&self, request: HueRequest) -> Result<HueResponse, HueError> {
+        if !matches!(request.method, Method::PUT | Method::POST | Method::DELETE) {
+            return self.inner.send(request).await;
+        }
+        if request.method == Method::POST && request.path() == "/api" {
+            return Err(HueError::Unsupported);
+        }
+
+        tracing::info!(method = %request.method, path = %request.redacted_path(), body = ?request.body, "dry run, not sent");
+        let response = dry_run_response(&request);
+        self.withheld.lock().unwrap().push(request);
+        Ok(response)
+    }
+
+    async fn stream(&self, request: HueRequest) -> Result<ByteStream, HueError> {
+        self.inner.stream(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_path() {
+        assert_eq!(HueRequest::new(Method::GET, "https://10.0.0.2/clip/v2/resource/light").path(), "/clip/v2/resource/light");
+        assert_eq!(HueRequest::new(Method::GET, "https://discovery.meethue.com").path(), "/");
+    }
+
+    #[tokio::test]
+    async fn failing_transport_never_sends() {
+        let request = HueRequest::new(Method::GET, "https://10.0.0.2/api/0/config");
+        assert!(matches!(FailingTransport.send(request.clone()).await, Err(HueError::ClientCreate)));
+        assert!(matches!(FailingTransport.stream(request).await, Err(HueError::ClientCreate)));
+    }
+
+    #[tokio::test]
+    async fn memory_routes_in_order() {
+        let transport = MemoryTransport::new()
+            .route(Method::GET, "/api/0/config", json!({ "first": true }))
+            .route(Method::GET, "/api/0/config", json!({ "first": false }));
+
+        let request = HueRequest::new(Method::GET, "https://10.0.0.2/api/0/config");
+        assert_eq!(transport.send(request.clone()).await.unwrap().body, json!({ "first": true }));
+        assert_eq!(transport.send(request.clone()).await.unwrap().body, json!({ "first": false }));
+        assert_eq!(transport.send(request).await.unwrap().body, json!({ "first": false }));
+
+        let missing = transport.send(HueRequest::new(Method::GET, "https://10.0.0.2/missing")).await.unwrap();
+        assert_eq!(missing.status, 404);
+        assert_eq!(transport.requests().len(), 4);
+    }
+}
@@ -0,0 +1,306 @@
+// Legacy v1 API backend, used automatically when the bridge doesn't support CLIP v2.
+// Resources keep their v1 path as id (e.g. "/lights/5"), matching `id_v1` on CLIP v2 bridges.
+
+use reqwest::Method;
+use serde_json::{json, Map, Value};
+
+use crate::{HueError, HueBridge, ResourceRef};
+use crate::capabilities::{Capabilities, ColorCapability, Gamut};
+use crate::light::{ColorXY, Light};
+use crate::room::{Room, RoomKind};
+use crate::scene::Scene;
+
+// v1 brightness is 1-254, CLIP v2 uses a 0-100 percentage.
+const MAX_BRI: f64 = 254.0;
+
+fn api_path(bridge: &HueBridge, resource: &str) -> String {
+    format!("/api/{}{}", bridge.username, resource)
+}
+
+async fn get_objects(bridge: &HueBridge, resource: &str) -> Result<Map<String, Value>, HueError> {
+    let response = bridge.request(Method::GET, &api_path(bridge, resource), None).await?;
+    check_errors(&response)?;
+
+    match response {
+        Value::Object(objects) => Ok(objects),
+        _ => Err(HueError::InvalidData { msg: format!("couldn't parse v1 {}", resource) }),
+    }
+}
+
+pub(crate) async fn list_lights(bridge: &HueBridge) -> Result<Vec<Light>, HueError> {
+    let lights = get_objects(bridge, "/lights").await?
+        .into_iter()
+        .filter_map(|(id, x)| {
+            let state = &x["state"];
+            let brightness = state["bri"].as_f64().map(|bri| bri / MAX_BRI * 100.0).unwrap_or(100.0);
+            let id_v1 = format!("/lights/{}", id);
+            Some(Light {
+                id: id_v1.clone(),
+                id_v1: Some(id_v1),
+                name: x["name"].as_str()?.into(),
+                is_on: state["on"].as_bool()?,
+                brightness,
+                min_brightness: 100.0 / MAX_BRI,
+                // Same as CLIP v2, lights without color report D65 white.
+                color: ColorXY {
+                    x: state["xy"][0].as_f64().unwrap_or(ColorXY::WHITE_X),
+                    y: state["xy"][1].as_f64().unwrap_or(ColorXY::WHITE_Y),
+                    bri: brightness
+                },
+                capabilities: light_capabilities(&x),
+                extras: Map::new(),
+            })
+        })
+        .collect();
+
+    Ok(lights)
+}
+
+// Translates a CLIP v2 light body into a v1 light state body.
+pub(crate) fn light_state(body: &Value) -> Value {
+    let mut state = Map::new();
+    if let Some(on) = body["on"]["on"].as_bool() {
+        state.insert("on".into(), json!(on));
+    }
+    if let Some(brightness) = body["dimming"]["brightness"].as_f64() {
+        state.insert("bri".into(), json!((brightness / 100.0 * MAX_BRI).round().clamp(1.0, MAX_BRI) as u8));
+    }
+    if let (Some(x), Some(y)) = (body["color"]["xy"]["x"].as_f64(), body["color"]["xy"]["y"].as_f64()) {
+        state.insert("xy".into(), json!([x, y]));
+    }
+    if let Some(mirek) = body["color_temperature"]["mirek"].as_u64() {
+        state.insert("ct".into(), json!(mirek));
+    }
+    state.into()
+}
+
+// v1 lights have no feature objects, only the state fields they support.
+fn light_capabilities(light: &Value) -> Capabilities {
+    let state = &light["state"];
+    let ct = &light["capabilities"]["control"]["ct"];
+    Capabilities {
+        dimming: state["bri"].is_number().then_some(100.0 / MAX_BRI..=100.0),
+        color_temperature: state["ct"].is_number().then(|| {
+            ct["min"].as_u64().unwrap_or(153) as u32..=ct["max"].as_u64().unwrap_or(500) as u32
+        }),
+        color: state["xy"].is_array().then(|| ColorCapability {
+            gamut: serde_json::from_value::<[[f64; 2]; 3]>(light["capabilities"]["control"]["colorgamut"].clone())
+                .ok()
+                .map(|[red, green, blue]| Gamut { red, green, blue }),
+            gamut_type: light["capabilities"]["control"]["colorgamuttype"].as_str().map(|gamut_type| gamut_type.into()),
+        }),
+        ..Capabilities::default()
+    }
+}
+
+// Current state of every light as CLIP v2 shaped json, keyed by v1 path.
+pub(crate) async fn light_states(bridge: &HueBridge) -> Result<Vec<(String, Value)>, HueError> {
+    let states = get_objects(bridge, "/lights").await?
+        .into_iter()
+        .map(|(id, x)| {
+            let state = &x["state"];
+            let mut body = json!({ "on": { "on": state["on"] } });
+            if let Some(bri) = state["bri"].as_f64() {
+                body["dimming"] = json!({ "brightness": bri / MAX_BRI * 100.0 });
+            }
+            if let (Some(x), Some(y)) = (state["xy"][0].as_f64(), state["xy"][1].as_f64()) {
+                body["color"] = json!({ "xy": { "x": x, "y": y } });
+            }
+            if let Some(ct) = state["ct"].as_u64() {
+                // As in CLIP v2, the temperature only counts while the light is in that mode.
+                body["color_temperature"] = json!({ "mirek": ct, "mirek_valid": state["colormode"] == "ct" });
+            }
+            (format!("/lights/{}", id), body)
+        })
+        .collect();
+
+    Ok(states)
+}
+
+// Renames and state changes go to different v1 endpoints, archetype and power-up can't be set.
+pub(crate) async fn put_light(bridge: &HueBridge, light_id: &str, body: &Value) -> Result<(), HueError> {
+    if !body["metadata"]["archetype"].is_null() || !body["powerup"].is_null() {
+        return Err(HueError::Unsupported);
+    }
+
+    let Some(name) = body["metadata"]["name"].as_str() else {
+        return put_light_state(bridge, light_id, body).await;
+    };
+
+    let response = bridge.request(Method::PUT, &api_path(bridge, light_id), Some(&json!({ "name": name }))).await?;
+    check_errors(&response)?;
+    if light_state(body) != json!({}) {
+        put_light_state(bridge, light_id, body).await?;
+    }
+    Ok(())
+}
+
+// `light_id` is the v1 path, e.g. "/lights/5".
+pub(crate) async fn put_light_state(bridge: &HueBridge, light_id: &str, body: &Value) -> Result<(), HueError> {
+    let req = api_path(bridge, &format!("{}/state", light_id));
+    let response = bridge.request(Method::PUT, &req, Some(&light_state(body))).await?;
+    check_errors(&response)
+}
+
+pub(crate) async fn list_groups(bridge: &HueBridge, kind: RoomKind) -> Result<Vec<Room>, HueError> {
+    let group_type = match kind {
+        RoomKind::Room => "Room",
+        RoomKind::Zone => "Zone",
+    };
+
+    let rooms = get_objects(bridge, "/groups").await?
+        .into_iter()
+        .filter(|(_, x)| x["type"].as_str() == Some(group_type))
+        .filter_map(|(id, x)| {
+            let children = x["lights"].as_array()?
+                .iter()
+                .filter_map(|light| light.as_str())
+                .map(|light| ResourceRef { rid: format!("/lights/{}", light), rtype: "light".into() })
+                .collect();
+
+            Some(Room {
+                id: format!("/groups/{}", id),
+                kind,
+                name: x["name"].as_str()?.into(),
+                archetype: x["class"].as_str().unwrap_or("other").to_lowercase().replace(' ', "_"),
+                children,
+                services: vec![ResourceRef { rid: format!("/groups/{}", id), rtype: "grouped_light".into() }],
+                extras: Map::new(),
+            })
+        })
+        .collect();
+
+    Ok(rooms)
+}
+
+pub(crate) async fn list_scenes(bridge: &HueBridge) -> Result<Vec<Scene>, HueError> {
+    let scenes = get_objects(bridge, "/scenes").await?;
+    // Group scenes point at a room or zone, the group type tells which.
+    let groups = match scenes.values().any(|x| x["group"].is_string()) {
+        true => get_objects(bridge, "/groups").await?,
+        false => Map::new(),
+    };
+
+    let scenes = scenes
+        .into_iter()
+        .filter_map(|(id, x)| {
+            let group = match x["group"].as_str() {
+                Some(group) => {
+                    let kind = match groups.get(group).and_then(|group| group["type"].as_str()) {
+                        Some("Zone") => RoomKind::Zone,
+                        _ => RoomKind::Room,
+                    };
+                    ResourceRef { rid: format!("/groups/{}", group), rtype: kind.rtype().into() }
+                },
+                None => ResourceRef { rid: "/groups/0".into(), rtype: "bridge_home".into() },
+            };
+
+            Some(Scene {
+                id: format!("/scenes/{}", id),
+                name: x["name"].as_str()?.into(),
+                group,
+                actions: Vec::new(),
+                extras: Map::new(),
+            })
+        })
+        .collect();
+
+    Ok(scenes)
+}
+
+// Recalling through group 0 applies the scene to its own lights, whichever group it belongs to.
+pub(crate) async fn recall_scene(bridge: &HueBridge, scene_id: &str) -> Result<(), HueError> {
+    let scene_id = scene_id.trim_start_matches("/scenes/");
+    let req = api_path(bridge, "/groups/0/action");
+    let response = bridge.request(Method::PUT, &req, Some(&json!({ "scene": scene_id }))).await?;
+    check_errors(&response)
+}
+
+// Sets one field of a sensor's config, checking first that the sensor has it.
+pub(crate) async fn put_sensor_config(bridge: &HueBridge, sensor_id: &str, field: &str, value: Value) -> Result<(), HueError> {
+    let sensor = bridge.request(Method::GET, &api_path(bridge, sensor_id), None).await?;
+    check_errors(&sensor)?;
+    if sensor["config"].get(field).is_none() {
+        return Err(HueError::Unsupported);
+    }
+
+    let req = api_path(bridge, &format!("{}/config", sensor_id));
+    let response = bridge.request(Method::PUT, &req, Some(&json!({ field: value }))).await?;
+    check_errors(&response)
+}
+
+// v1 responses report failures as `[{ "error": { "description": ... } }]`.
+pub(crate) fn check_errors(response: &Value) -> Result<(), HueError> {
+    let Some(results) = response.as_array() else { return Ok(()); };
+
+    let errors = results.iter()
+        .filter_map(|result| result["error"]["description"].as_str())
+        .collect::<Vec<_>>();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(HueError::Bridge { msg: errors.join(", ") })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::ApiVersion;
+    use crate::transport::MemoryTransport;
+
+    #[test]
+    fn translates_light_state() {
+        let body = json!({
+            "on": { "on": true },
+            "dimming": { "brightness": 50.0 },
+            "color": { "xy": { "x": 0.4, "y": 0.3 } }
+        });
+        assert_eq!(light_state(&body), json!({ "on": true, "bri": 127, "xy": [0.4, 0.3] }));
+        assert_eq!(light_state(&json!({ "dimming": { "brightness": 0.0 } })), json!({ "bri": 1 }));
+    }
+
+    #[tokio::test]
+    async fn lists_dimmable_light() {
+        let transport = Arc::new(MemoryTransport::new()
+            .route(Method::GET, "/api/test-key/lights", json!({
+                "2": { "name": "Hallway", "type": "Dimmable light", "state": { "on": true, "bri": 127, "alert": "none", "reachable": true } }
+            })));
+        let bridge = HueBridge::new("10.0.0.2".into(), "test-key".into())
+            .with_api_version(ApiVersion::V1)
+            .with_transport(transport);
+
+        let light = &list_lights(&bridge).await.unwrap()[0];
+        assert!(light.capabilities.is_dimmable() && !light.capabilities.has_color());
+        assert_eq!((light.color.x, light.color.y), (ColorXY::WHITE_X, ColorXY::WHITE_Y));
+        let rgb = light.color.as_rgb();
+        assert!(rgb.r.is_finite() && rgb.g.is_finite() && rgb.b.is_finite());
+    }
+
+    #[tokio::test]
+    async fn lists_zone_scenes() {
+        let transport = Arc::new(MemoryTransport::new()
+            .route(Method::GET, "/api/test-key/scenes", json!({
+                "abc": { "name": "Relax", "type": "GroupScene", "group": "3" },
+                "def": { "name": "Read", "type": "LightScene" }
+            }))
+            .route(Method::GET, "/api/test-key/groups", json!({ "3": { "name": "Upstairs", "type": "Zone", "lights": ["1"] } })));
+        let bridge = HueBridge::new("10.0.0.2".into(), "test-key".into())
+            .with_api_version(ApiVersion::V1)
+            .with_transport(transport);
+
+        let scenes = list_scenes(&bridge).await.unwrap();
+        let relax = scenes.iter().find(|scene| scene.name == "Relax").unwrap();
+        assert_eq!(relax.group, ResourceRef { rid: "/groups/3".into(), rtype: "zone".into() });
+        assert_eq!(scenes.iter().find(|scene| scene.name == "Read").unwrap().group.rtype, "bridge_home");
+    }
+
+    #[test]
+    fn reports_errors() {
+        let response = json!([{ "error": { "type": 3, "address": "/lights/9", "description": "resource, /lights/9, not available" } }]);
+        assert!(check_errors(&response).is_err());
+        assert!(check_errors(&json!([{ "success": { "/lights/1/state/on": true } }])).is_ok());
+    }
+}
@@ -0,0 +1,146 @@
+// Application keys registered on a bridge, the "whitelist" of the v1 config. Every pairing adds
+// one, so test runs and reinstalls leave old keys behind.
+
+use reqwest::Method;
+use serde_json::Value;
+
+use crate::{HueError, HueBridge, v1};
+use crate::pairing::{PairOptions, PairProgress};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApplicationKey {
+    pub username: String,
+    // The devicetype given when pairing, e.g. "huey#desktop".
+    pub name: String,
+    // UTC without a timezone, e.g. "2023-03-01T10:00:00".
+    pub created: Option<String>,
+    pub last_used: Option<String>,
+    // Whether this is the key the bridge handle uses.
+    pub current: bool,
+}
+
+#[derive(Debug)]
+pub struct KeyRotation {
+    // Uses the new key, store it in place of the old bridge.
+    pub bridge: HueBridge,
+    // The new key works either way, a failed revoke only leaves the old key registered.
+    pub revoked: Result<(), HueError>,
+}
+
+impl HueBridge {
+    // Available on v1 and CLIP v2 bridges, oldest first.
+    pub async fn application_keys(&self) -> Result<Vec<ApplicationKey>, HueError> {
+        let response = self.whitelist_request(Method::GET, "").await?;
+
+        let Value::Object(whitelist) = &response["whitelist"] else {
+            return Err(HueError::InvalidData { msg: "couldn't parse whitelist".into() });
+        };
+        let mut keys = whitelist.iter()
+            .map(|(username, entry)| ApplicationKey {
+                username: username.clone(),
+                name: entry["name"].as_str().unwrap_or_default().into(),
+                created: entry["create date"].as_str().map(|date| date.into()),
+                last_used: entry["last use date"].as_str().map(|date| date.into()),
+                current: *username == self.username,
+            })
+            .collect::<Vec<_>>();
+        keys.sort_by(|a, b| a.created.cmp(&b.created));
+        Ok(keys)
+    }
+
+    // Newer firmware may refuse this and only allow revoking keys from the Hue account.
+    pub async fn revoke_application_key(&self, username: &str) -> Result<(), HueError> {
+        self.whitelist_request(Method::DELETE, &format!("/whitelist/{}", username)).await?;
+        Ok(())
+    }
+
+    // `path` is relative to the v1 config. The api version picks the scheme, v1 bridges only serve http.
+    async fn whitelist_request(&self, method: Method, path: &str) -> Result<Value, HueError> {
+        self.api_version().await?;
+        let response = self.request(method, &format!("/api/{}/config{}", self.username, path), None).await?;
+        v1::check_errors(&response)?;
+        Ok(response)
+    }
+
+    // Pairs a new key, which needs the link button, checks that it works and then revokes the
+    // key this bridge handle uses.
+    #[tracing::instrument(name = "rotate_key", skip_all, fields(bridge_ip = %self.bridge_ip()))]
+    pub async fn rotate_key(&self, options: PairOptions, on_progress: impl FnMut(PairProgress)) -> Result<KeyRotation, HueError> {
+        let options = match options.transport {
+            Some(_) => options,
+            None => options.transport(self.transport().clone()),
+        };
+        let options = match options.config {
+            Some(_) => options,
+            None => options.config(self.config().clone()),
+        };
+        let bridge = HueBridge::pair_with(self.bridge_ip(), options, on_progress).await?;
+
+        // Listing the keys only works with a valid key, and is done with the new one.
+        bridge.application_keys().await?;
+        let revoked = bridge.revoke_application_key(&self.username).await;
+        if let Err(err) = &revoked {
+            tracing::warn!(error = %err, "couldn't revoke old key");
+        }
+        Ok(KeyRotation { bridge, revoked })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::config::{ClientConfig, RetryPolicy};
+    use crate::tests::memory_bridge;
+    use crate::transport::MemoryTransport;
+
+    fn config() -> Value {
+        json!({ "name": "Hue", "whitelist": {
+            "test-key": { "name": "huey#desktop", "create date": "2023-03-01T10:00:00", "last use date": "2023-03-02T08:00:00" },
+            "old-key": { "name": "huey#test", "create date": "2022-01-01T10:00:00", "last use date": "2022-01-01T10:00:00" }
+        }})
+    }
+
+    #[tokio::test]
+    async fn lists_application_keys() {
+        let (bridge, _) = memory_bridge(MemoryTransport::new()
+            .route(Method::GET, "/api/test-key/config", config()));
+
+        let keys = bridge.application_keys().await.unwrap();
+        assert_eq!(keys.iter().map(|key| key.name.as_str()).collect::<Vec<_>>(), vec!["huey#test", "huey#desktop"]);
+        assert!(keys[1].current && !keys[0].current);
+        assert_eq!(keys[1].last_used.as_deref(), Some("2023-03-02T08:00:00"));
+    }
+
+    #[tokio::test]
+    async fn lists_keys_over_http() {
+        let transport = Arc::new(MemoryTransport::new()
+            .route(Method::GET, "http://10.0.0.2/api/0/config", json!({ "name": "Hue", "bridgeid": "ECB5FAFFFE000001", "apiversion": "1.16.0", "swversion": "01041302", "modelid": "BSB001" }))
+            .route(Method::GET, "http://10.0.0.2/api/test-key/config", config())
+            .route(Method::DELETE, "http://10.0.0.2/api/test-key/config/whitelist/old-key", json!([{ "success": "/config/whitelist/old-key deleted" }]))
+            .with_fallback(|request| Err(HueError::Unreachable { msg: request.url.clone() })));
+        let bridge = HueBridge::new("10.0.0.2".into(), "test-key".into())
+            .with_transport(transport)
+            .with_config(ClientConfig::default().retry(RetryPolicy::none()));
+
+        assert_eq!(bridge.application_keys().await.unwrap().len(), 2);
+        bridge.revoke_application_key("old-key").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rotates_key() {
+        let (bridge, transport) = memory_bridge(MemoryTransport::new()
+            .route(Method::POST, "/api", json!([{ "success": { "username": "new-key" } }]))
+            .route(Method::GET, "/api/0/config", json!({ "name": "Hue", "bridgeid": "ECB5FAFFFE000001", "apiversion": "1.60.0", "swversion": "1960135080", "modelid": "BSB002" }))
+            .route(Method::GET, "/api/new-key/config", config())
+            .route(Method::DELETE, "/api/new-key/config/whitelist/test-key", json!([{ "success": "/config/whitelist/test-key deleted" }])));
+
+        let rotation = bridge.rotate_key(PairOptions::new("huey", "desktop").generate_client_key(false), |_| {}).await.unwrap();
+        assert_eq!(rotation.bridge.username, "new-key");
+        assert!(rotation.revoked.is_ok());
+        assert_eq!(transport.requests().last().unwrap().method, Method::DELETE);
+    }
+}
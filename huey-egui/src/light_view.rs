@@ -1,39 +1,80 @@
+use std::sync::Arc;
+
 use eframe::egui::color_picker::color_edit_button_rgb;
-use huey_core::{HueBridge, light::{Light, Color, ColorRGB}};
+use huey_core::{HueBridge, HueError, light::{Light, Color, ColorRGB}, state::{HueState, StateSnapshot}};
 use poll_promise::Promise;
+use tokio::sync::watch;
 
 use crate::toggle_switch::toggle_ui;
 use crate::egui::Slider;
 use crate::Result;
 
-pub struct LightsViewModel(Option<Promise<Result<Vec<LightViewModel>>>>);
+// Lights either come from the live state mirror or, on v1 bridges without an event stream, a single listing.
+enum LightsSource {
+    Live(watch::Receiver<Arc<StateSnapshot>>),
+    Listed(Option<Vec<Light>>),
+}
+
+pub struct LightsViewModel {
+    source: Option<Promise<Result<LightsSource>>>,
+    revision: Option<u64>,
+    lights: Vec<LightViewModel>,
+}
 
 impl LightsViewModel {
     pub fn new() -> Self {
-        Self(None)
+        Self { source: None, revision: None, lights: Vec::new() }
     }
 
     pub fn ui(&mut self, bridge: &HueBridge, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
-        // Get or create async request to get lights from bridge
-        let lights_promise = self.0.get_or_insert_with(|| {
+        // Get or create async request to load the bridge state
+        let source_promise = self.source.get_or_insert_with(|| {
             let bridge = bridge.clone();
+            let context = ui.ctx().clone();
             Promise::spawn_async(async move {
-                Light::list_lights(bridge).await.map(|lights| {
-                    lights.into_iter().map(|light| {
-                        LightViewModel::new(light)
-                    }).collect()
-                })
+                match HueState::load(bridge.clone()).await {
+                    Ok(state) => {
+                        // Keep the state in sync in the background and repaint whenever it changes
+                        let mut changes = state.subscribe();
+                        tokio::spawn(async move {
+                            while changes.changed().await.is_ok() {
+                                context.request_repaint();
+                            }
+                        });
+                        let receiver = state.subscribe();
+                        tokio::spawn(async move { state.run().await });
+                        Ok(LightsSource::Live(receiver))
+                    },
+                    Err(HueError::Unsupported) => Light::list_lights(&bridge).await.map(|lights| LightsSource::Listed(Some(lights))),
+                    Err(err) => Err(err),
+                }
             })
         });
 
         // Display ui spinner if promise isn't ready
-        let Some(Ok(lights)) = lights_promise.ready_mut() else {
+        let Some(Ok(source)) = source_promise.ready_mut() else {
             return ui.spinner();
         };
 
+        // Pick up changes from the bridge
+        match source {
+            LightsSource::Live(receiver) => {
+                let snapshot = receiver.borrow().clone();
+                if self.revision != Some(snapshot.revision()) {
+                    self.revision = Some(snapshot.revision());
+                    sync_lights(&mut self.lights, snapshot.lights());
+                }
+            },
+            LightsSource::Listed(lights) => {
+                if let Some(lights) = lights.take() {
+                    sync_lights(&mut self.lights, lights);
+                }
+            },
+        }
+
         // Draw ui for the list of available lights
         ui.vertical(|ui| {
-            lights.iter_mut().for_each(|viewmodel| {
+            self.lights.iter_mut().for_each(|viewmodel| {
                 viewmodel.ui(bridge, ui);
             });    
         }).response
@@ -41,6 +82,17 @@ impl LightsViewModel {
 
 }
 
+// Updates existing view models in place so pending requests survive a refresh.
+fn sync_lights(viewmodels: &mut Vec<LightViewModel>, lights: Vec<Light>) {
+    viewmodels.retain(|viewmodel| lights.iter().any(|light| light.id == viewmodel.light.id));
+    for light in lights {
+        match viewmodels.iter_mut().find(|viewmodel| viewmodel.light.id == light.id) {
+            Some(viewmodel) => viewmodel.sync(light),
+            None => viewmodels.push(LightViewModel::new(light)),
+        }
+    }
+}
+
 pub struct LightViewModel {
     light: Light,
     toggle_promise: Option<Promise<Option<bool>>>,
@@ -65,6 +117,14 @@ impl LightViewModel {
         }
     }
 
+    // Takes the latest state from the bridge.
+    pub fn sync(&mut self, light: Light) {
+        let color_rgb = light.color.as_rgb();
+        self.brightness = light.brightness;
+        self.color = [color_rgb.r as f32, color_rgb.g as f32, color_rgb.b as f32];
+        self.light = light;
+    }
+
     pub fn ui(&mut self, bridge: &HueBridge, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
         ui.horizontal(|ui| {
             // Draw ui for nice toggle switch
@@ -91,14 +151,16 @@ impl LightViewModel {
                 }));
             }
 
-            // Draw ui for color picker
-            color_edit_button_rgb(ui, &mut self.color);
+            // Draw ui for color picker, only for lights that support color
+            if self.light.capabilities.has_color() {
+                color_edit_button_rgb(ui, &mut self.color);
+            }
 
             // Convert ui color to Hue compatible CIE XY color
             let color = ColorRGB { r: self.color[0].into(), g: self.color[1].into(), b: self.color[2].into() }.as_xy();
 
             // Has color changed?
-            if self.light.color.x != color.x && self.light.color.y != color.y {
+            if self.light.capabilities.has_color() && self.light.color.x != color.x && self.light.color.y != color.y {
                 // Cancel previous request
                 if self.color_promise.is_some() {
                     self.color_promise.take().unwrap().abort();
@@ -122,12 +184,14 @@ impl LightViewModel {
                 }));
             }
 
-            // Draw ui for brightness slider
-            ui.add(
-                Slider::new(&mut self.brightness, self.light.min_brightness..=100.0)
-                    .step_by(10.0)
-                    .show_value(false)
-            );
+            // Draw ui for brightness slider, only for dimmable lights
+            if let Some(range) = self.light.capabilities.dimming.clone() {
+                ui.add(
+                    Slider::new(&mut self.brightness, range)
+                        .step_by(10.0)
+                        .show_value(false)
+                );
+            }
 
             // Has brightness changed?
             if self.brightness != self.light.brightness {
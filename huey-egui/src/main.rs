@@ -2,7 +2,7 @@
 
 use crossbeam_channel::Receiver;
 use eframe::{egui::{self, CentralPanel}, Storage, epaint::{Pos2, Vec2}, IconData};
-use huey_core::{HueError, HueBridge};
+use huey_core::{BridgeEvent, HueError, HueBridge, pairing::PairOptions, store::CredentialStore};
 use light_view::LightsViewModel;
 use poll_promise::Promise;
 use tray_icon::{TrayIconBuilder, TrayEvent, ClickEvent, TrayIcon, menu::{Menu, MenuItem, MenuEvent}};
@@ -14,8 +14,9 @@ pub type Result<T> = std::result::Result<T, HueError>;
 
 const WIDTH: f32 = 300.0;
 const HEIGHT: f32 = 200.0;
-const BRIDGE_IP_KEY: &'static str = "bridge_ip";
-const BRIDGE_KEY_KEY: &'static str = "bridge_key";
+// Where earlier versions kept the bridge in eframe's storage, moved to the credential store.
+const LEGACY_IP_KEY: &str = "bridge_ip";
+const LEGACY_KEY_KEY: &str = "bridge_key";
 
 #[tokio::main]
 async fn main() -> std::result::Result<(), eframe::Error> {
@@ -63,6 +64,8 @@ struct HueyApp {
     bridge_ip: Option<Promise<Result<String>>>,
     bridge: Option<Promise<Result<HueBridge>>>,
     light_viewmodel: LightsViewModel,
+    bridge_events: Option<tokio::sync::broadcast::Receiver<BridgeEvent>>,
+    bridge_notice: Option<String>,
     tray_receiver: Receiver<TrayEvent>,
     menu_receiver: Receiver<MenuEvent>,
     _tray_icon: TrayIcon
@@ -70,20 +73,20 @@ struct HueyApp {
 
 impl HueyApp {
     fn new(_cc: &eframe::CreationContext<'_>, icon: tray_icon::icon::Icon) -> HueyApp {
-        // Load previous bridge from storage if possible.
-        let bridge = _cc.storage.and_then(|store| {
-            if let Some(bridge_ip) = store.get_string(BRIDGE_IP_KEY) {
-                if let Some(bridge_key) = store.get_string(BRIDGE_KEY_KEY) {
-                    return Some(HueBridge::new(bridge_ip, bridge_key));
-                }
-            }
-            None
-        });
+        // Load previous bridge from the shared credential store if possible.
+        let bridge = CredentialStore::load().ok().and_then(|store| store.default_bridge().ok().flatten());
 
         // Convert storage values to promises
         let (bridge_ip, bridge) = if let Some(bridge) = bridge {
-            (Some(Promise::from_ready(Ok(bridge.bridge_ip.clone()))),
+            (Some(Promise::from_ready(Ok(bridge.bridge_ip()))),
             Some(Promise::from_ready(Ok(bridge))))
+        } else if let Some((bridge_ip, bridge_key)) = _cc.storage.and_then(legacy_bridge) {
+            // The store needs the bridge id, which the old storage didn't keep. Saved on the next autosave.
+            (Some(Promise::from_ready(Ok(bridge_ip.clone()))),
+            Some(Promise::spawn_async(async move {
+                let config = HueBridge::fetch_config(&bridge_ip).await?;
+                Ok(HueBridge::new(bridge_ip, bridge_key).with_bridge_id(config.bridge_id))
+            })))
         } else { (None, None) };
 
         // Create quit tray button for windows/linux
@@ -131,6 +134,8 @@ impl HueyApp {
             bridge_ip,
             bridge,
             light_viewmodel: LightsViewModel::new(),
+            bridge_events: None,
+            bridge_notice: None,
             tray_receiver,
             menu_receiver,
             _tray_icon: tray_icon
@@ -140,7 +145,7 @@ impl HueyApp {
     fn pair_bridge(bridge_ip: &String) -> Promise<Result<HueBridge>> {
         let bridge_ip = bridge_ip.clone();
         Promise::spawn_async(async move { 
-            HueBridge::pair(bridge_ip).await
+            HueBridge::pair_with(bridge_ip, PairOptions::new("huey", "egui"), |_| {}).await
         })
     }
 }
@@ -214,6 +219,15 @@ impl eframe::App for HueyApp {
             };
 
             if let Ok(bridge) = bridge_result {
+                // Show a notice when the bridge was rediscovered at a new ip.
+                let events = self.bridge_events.get_or_insert_with(|| bridge.subscribe());
+                if let Ok(BridgeEvent::IpChanged { new_ip, .. }) = events.try_recv() {
+                    self.bridge_notice = Some(format!("Bridge moved to {}", new_ip));
+                }
+                if let Some(notice) = &self.bridge_notice {
+                    ui.label(notice);
+                }
+
                 // Display light_view.rs when bridge is connected and paired.
                 self.light_viewmodel.ui(bridge, ui);
             } else {
@@ -228,11 +242,31 @@ impl eframe::App for HueyApp {
         });
     }
 
-    fn save(&mut self, _storage: &mut dyn Storage) {
-        // If bridge is connected, save known ip and api key to storage.
+    fn save(&mut self, storage: &mut dyn Storage) {
+        // If bridge is connected, save known ip and api key to the shared credential store.
         let Some(bridge_key_opt) = &self.bridge else { return; };
         let Some(Ok(bridge)) = bridge_key_opt.ready() else { return; };
-        _storage.set_string(BRIDGE_IP_KEY, bridge.bridge_ip.clone());
-        _storage.set_string(BRIDGE_KEY_KEY, bridge.username.clone());
+        let saved = CredentialStore::load().and_then(|mut store| {
+            store.remember(bridge)?;
+            store.save()
+        });
+
+        // Shown next to the lights, the bridge has to be paired again next launch otherwise.
+        match saved {
+            // eframe can't remove keys, empty values are treated as missing.
+            Ok(()) if legacy_bridge(storage).is_some() => {
+                storage.set_string(LEGACY_IP_KEY, String::new());
+                storage.set_string(LEGACY_KEY_KEY, String::new());
+            },
+            Ok(()) => {},
+            Err(err) => self.bridge_notice = Some(format!("Couldn't save bridge: {}", err)),
+        }
     }
 }
+
+// The ip and application key saved by versions before the credential store.
+fn legacy_bridge(storage: &dyn Storage) -> Option<(String, String)> {
+    let bridge_ip = storage.get_string(LEGACY_IP_KEY).filter(|bridge_ip| !bridge_ip.is_empty())?;
+    let bridge_key = storage.get_string(LEGACY_KEY_KEY).filter(|bridge_key| !bridge_key.is_empty())?;
+    Some((bridge_ip, bridge_key))
+}
@@ -1,8 +1,8 @@
 use iced::window::Icon;
 use iced_aw::Spinner;
-use iced::widget::{column,container};
+use iced::widget::{column,container,row,text,toggler,Column};
 use iced::{ Application, executor, Length, Theme, Command, Settings, Element };
-use huey_core::{HueBridge, HueError, light::Light};
+use huey_core::{HueBridge, HueError, light::Light, store::CredentialStore};
 use iced_native::{command::Action, window};
 use tray::{HueyTray, HueyTrayEvent};
 use tray_icon::ClickEvent;
@@ -51,7 +51,6 @@ enum State {
     Paired,
     LinkButtonNotPressed,
     Failed,
-    #[allow(dead_code)]
     Dashboard { lights: Vec<Light> }
 }
 
@@ -61,11 +60,26 @@ pub enum Message {
     PairBridge(Result<HueBridge, HueError>),
     ListLights(Result<Vec<Light>, HueError>),
     TrayEvent(HueyTrayEvent),
-    ChangePower(bool),
+    ChangePower(String, bool),
+    // The light id and the power it was changed to.
+    PowerChanged(String, bool, Result<(), HueError>),
     ChangeColor,
     None
 }
 
+// Brightness is only shown for dimmable lights, on/off plugs get just the toggle.
+fn light_row(light: &Light) -> Element<Message> {
+    let light_id = light.id.clone();
+    let mut controls = row![
+        toggler(light.name.clone(), light.is_on, move |is_on| Message::ChangePower(light_id.clone(), is_on))
+    ].spacing(10);
+
+    if light.capabilities.is_dimmable() {
+        controls = controls.push(text(format!("{:.0}%", light.brightness)));
+    }
+    controls.into()
+}
+
 struct HueyApp {
     tray: HueyTray,
     bridge: Option<HueBridge>,
@@ -73,6 +87,15 @@ struct HueyApp {
     state: State
 }
 
+impl HueyApp {
+    fn light_mut(&mut self, light_id: &str) -> Option<&mut Light> {
+        match &mut self.state {
+            State::Dashboard { lights } => lights.iter_mut().find(|light| light.id == light_id),
+            _ => None,
+        }
+    }
+}
+
 impl Application for HueyApp {
     type Message = Message;
     type Theme = Theme;
@@ -80,12 +103,22 @@ impl Application for HueyApp {
     type Flags = tray_icon::icon::Icon;
 
     fn new(flags: Self::Flags) -> (Self, Command<Message>) {
+        // Reuse a bridge paired by any huey frontend before searching for one.
+        let bridge = CredentialStore::load().ok().and_then(|store| store.default_bridge().ok().flatten());
+        let (state, command) = match &bridge {
+            Some(bridge) => {
+                let bridge = bridge.clone();
+                (State::Paired, Command::perform(async move { Light::list_lights(&bridge).await }, Message::ListLights))
+            },
+            None => (State::Search, Command::perform(HueBridge::discover(), Message::DiscoverBridge)),
+        };
+
         (Self {
             tray: HueyTray::new(flags),
-            bridge: None,
+            bridge,
             is_visible: false,
-            state: State::Search,
-        }, Command::perform(HueBridge::discover(), Message::DiscoverBridge))
+            state,
+        }, command)
     }
 
     fn title(&self) -> String {
@@ -108,6 +141,9 @@ impl Application for HueyApp {
             Message::PairBridge(result) => {
                 match result {
                     Ok(bridge) => {
+                        if let Ok(mut store) = CredentialStore::load() {
+                            store.remember(&bridge).and_then(|_| store.save()).ok();
+                        }
                         self.bridge = Some(bridge.clone());
                         self.state = State::Paired;
                         // return Command::perform(Light::list_lights(bridge), Message::ListLights);
@@ -153,19 +189,42 @@ impl Application for HueyApp {
             Message::TrayEvent(HueyTrayEvent::MenuEvent(_)) => {
                 return Command::single(Action::Window(window::Action::Close))
             },
+            Message::ChangePower(light_id, is_on) => {
+                if let Some(light) = self.light_mut(&light_id) {
+                    light.is_on = is_on;
+                }
+                if let Some(bridge) = self.bridge.clone() {
+                    let result_id = light_id.clone();
+                    return Command::perform(
+                        async move { Light::toggle_power_id(light_id, is_on).on(&bridge).await },
+                        move |result| Message::PowerChanged(result_id, is_on, result)
+                    );
+                }
+            },
+            // The toggle was flipped before sending, put it back if the bridge didn't take it.
+            Message::PowerChanged(light_id, is_on, Err(_)) => {
+                if let Some(light) = self.light_mut(&light_id) {
+                    light.is_on = !is_on;
+                }
+            },
             _ => {},
         }
         Command::none()
     }
 
     fn view(&self) -> Element<Message> {
-        match self {
+        match &self.state {
             // LightRS::BridgeSearch => todo!(),
             // LightRS::BridgeFound => todo!(),
             // LightRS::BridgePaired { bridge } => todo!(),
             // LightRS::LinkButtonNotPressed => todo!(),
             // LightRS::Failed => todo!(),
-            // LightRS::Dashboard { bridge, lights } => todo!(),
+            State::Dashboard { lights } => {
+                Column::with_children(lights.iter().map(light_row).collect())
+                    .spacing(8)
+                    .padding(10)
+                    .into()
+            },
             _ => {
                 column![
                     container(Spinner::new())